@@ -0,0 +1,56 @@
+//! Feeds arbitrary output through `VtSplitter` in arbitrary reads and checks that
+//! nothing is lost or reordered and that every chunk it lets out is whole.
+//!
+//! Run with `cargo fuzz run vt_splitter` from `src-tauri/fuzz`. The seed corpus
+//! covers the sequence types the splitter knows about.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+#[path = "../../src/vt_splitter.rs"]
+#[allow(dead_code)]
+mod vt_splitter;
+
+use vt_splitter::{VtSplitter, DEFAULT_MAX_SEQUENCE_BYTES};
+
+/// Cut `data` into reads of pseudo-random sizes derived from `seed`.
+fn reads(data: &[u8], seed: u8) -> Vec<&[u8]> {
+    let mut reads = Vec::new();
+    let mut rest = data;
+    let mut x = seed as usize | 1;
+    while !rest.is_empty() {
+        x = x.wrapping_mul(1103515245).wrapping_add(12345);
+        let n = (x >> 16) % 64 + 1;
+        let (read, tail) = rest.split_at(n.min(rest.len()));
+        reads.push(read);
+        rest = tail;
+    }
+    reads
+}
+
+fn run(data: &[u8], seed: u8, max_sequence_bytes: usize) -> Vec<Vec<u8>> {
+    let mut splitter = VtSplitter::new(max_sequence_bytes);
+    let mut chunks: Vec<Vec<u8>> = reads(data, seed).into_iter().map(|read| splitter.split(read)).collect();
+    chunks.push(splitter.finish());
+    chunks
+}
+
+fuzz_target!(|input: &[u8]| {
+    let Some((&seed, data)) = input.split_first() else {
+        return;
+    };
+
+    // With room for anything, every chunk but the last ends outside any sequence or character
+    let chunks = run(data, seed, DEFAULT_MAX_SEQUENCE_BYTES.max(data.len()));
+    assert_eq!(chunks.concat(), data);
+    let (rest, whole) = chunks.split_last().unwrap();
+    for chunk in whole {
+        assert_eq!(&VtSplitter::new(usize::MAX).split(chunk), chunk);
+    }
+    assert!(rest.is_empty() || VtSplitter::new(usize::MAX).split(rest).len() < rest.len());
+
+    // A small cap lets sequences through unfinished, but still loses nothing
+    let chunks = run(data, seed, seed as usize);
+    assert_eq!(chunks.concat(), data);
+});
@@ -0,0 +1,72 @@
+# Shell integration for Jeonghyeon terminals, loaded with `bash --init-file`.
+# Marks prompts and commands with OSC 133 and reports the directory with OSC 7.
+
+# --init-file replaces the usual startup files, so load them ourselves
+if [ -n "$JEONGHYEON_BASH_LOGIN" ]; then
+    unset JEONGHYEON_BASH_LOGIN
+    [ -r /etc/profile ] && . /etc/profile
+    if [ -r ~/.bash_profile ]; then
+        . ~/.bash_profile
+    elif [ -r ~/.bash_login ]; then
+        . ~/.bash_login
+    elif [ -r ~/.profile ]; then
+        . ~/.profile
+    fi
+else
+    [ -r ~/.bashrc ] && . ~/.bashrc
+fi
+
+if [ -z "$__jh_integrated" ]; then
+    __jh_integrated=1
+
+    __jh_urlencode() {
+        local LC_ALL=C s="$1" out="" c i
+        for (( i = 0; i < ${#s}; i++ )); do
+            c="${s:i:1}"
+            case "$c" in
+                [a-zA-Z0-9.~_/-]) out+="$c" ;;
+                *) printf -v c '%%%02X' "'$c"; out+="$c" ;;
+            esac
+        done
+        printf '%s' "$out"
+    }
+
+    __jh_precmd() {
+        local ret=$?
+        __jh_at_prompt=
+        # The backend ignores a finish marker when no command was started
+        printf '\e]133;D;%s\a' "$ret"
+        printf '\e]7;file://%s%s\a' "$HOSTNAME" "$(__jh_urlencode "$PWD")"
+        case "$PS1" in
+            *'133;A'*) ;;
+            *) PS1='\[\e]133;A\a\]'"$PS1"'\[\e]133;B\a\]' ;;
+        esac
+        return $ret
+    }
+
+    # Runs after a line is read and before it runs
+    __jh_preexec() {
+        local line
+        line=$(HISTTIMEFORMAT= builtin history 1)
+        line="${line#*[0-9]  }"
+        printf '\e]133;C;cmdline_url=%s\a' "$(__jh_urlencode "$line")"
+    }
+
+    # Before bash 4.4 there is no PS0, so the DEBUG trap stands in for it. It runs before
+    # every simple command, so only the first one after the prompt counts. This replaces
+    # any DEBUG trap set by the startup files.
+    __jh_debug() {
+        if [ -n "$__jh_at_prompt" ] && [ "$BASH_COMMAND" != __jh_precmd ]; then
+            __jh_at_prompt=
+            __jh_preexec
+        fi
+    }
+
+    PROMPT_COMMAND="__jh_precmd${PROMPT_COMMAND:+; $PROMPT_COMMAND}"
+    if (( BASH_VERSINFO[0] > 4 || (BASH_VERSINFO[0] == 4 && BASH_VERSINFO[1] >= 4) )); then
+        PS0='$(__jh_preexec)'"${PS0-}"
+    else
+        PROMPT_COMMAND="$PROMPT_COMMAND; __jh_at_prompt=1"
+        trap '__jh_debug' DEBUG
+    fi
+fi
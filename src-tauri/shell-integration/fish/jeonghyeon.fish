@@ -0,0 +1,19 @@
+# Shell integration for Jeonghyeon terminals, loaded with `fish --init-command`.
+# Marks prompts and commands with OSC 133 and reports the directory with OSC 7.
+
+if not set -q __jh_integrated
+    set -g __jh_integrated 1
+
+    function __jh_prompt_start --on-event fish_prompt
+        printf '\e]7;file://%s%s\a' $hostname (string escape --style=url -- $PWD)
+        printf '\e]133;A\a'
+    end
+
+    function __jh_preexec --on-event fish_preexec
+        printf '\e]133;C;cmdline_url=%s\a' (string escape --style=url -- $argv[1])
+    end
+
+    function __jh_postexec --on-event fish_postexec
+        printf '\e]133;D;%s\a' $status
+    end
+end
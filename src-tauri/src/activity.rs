@@ -0,0 +1,188 @@
+//! Guesses whether a session is busy, waiting on an answer or done, so agents like
+//! Claude Code, Codex and Gemini CLI can be watched without switching to their tabs.
+
+use crate::{ansi, process_info::ProcessInfo};
+use serde::Serialize;
+use std::time::Duration;
+
+/// How often every session is classified again.
+pub const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Output arriving this soon after input is its echo rather than a sign of work.
+pub const ECHO_WINDOW: Duration = Duration::from_millis(250);
+/// Output this recent means something is still running. Agents redraw a spinner while they work.
+const WORKING_QUIET: Duration = Duration::from_millis(1500);
+/// A question has to stay on screen this long before it counts, so logs that merely mention one don't.
+const PROMPT_SETTLE: Duration = Duration::from_millis(500);
+/// Output searched for questions, counted back from the end of the stream.
+pub const PROMPT_SCAN_BYTES: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityState {
+    /// Output is flowing, or a job other than an agent is in the foreground
+    Working,
+    /// Something on screen is asking a question: an approval, y/n, a password
+    WaitingForInput,
+    /// At a shell prompt, or an agent finished its turn and sits at its input box
+    Idle,
+    Exited,
+}
+
+/// Agents recognized by their executable or script name.
+const AGENTS: &[&str] = &["claude", "codex", "gemini"];
+
+/// Text that means the program is waiting on an answer. Matched ignoring case and
+/// spacing, since TUIs often move the cursor instead of printing spaces.
+const INPUT_PROMPTS: &[&str] = &[
+    // Claude Code
+    "do you want to proceed?",
+    "do you want to make this edit",
+    "do you want to create",
+    "❯ 1. yes",
+    // Codex
+    "allow command?",
+    "would you like to run the following command?",
+    "would you like to make the following edits?",
+    // Gemini CLI
+    "allow execution",
+    "apply this change?",
+    "waiting for user confirmation",
+    // Everything else
+    "(y/n)",
+    "[y/n]",
+    "(yes/no)",
+    "password:",
+    "password for",
+    "passphrase for",
+    "press enter to continue",
+    "press any key",
+];
+
+/// What is known about a session at one point in time.
+pub struct ActivitySample<'a> {
+    pub ended: bool,
+    /// Time since output that wasn't echoed input, `None` if there was none yet
+    pub quiet_for: Option<Duration>,
+    /// Output since the user last typed, at most [`PROMPT_SCAN_BYTES`] of it
+    pub recent_output: &'a [u8],
+    /// The foreground job, `None` when the shell itself is in the foreground
+    pub job: Option<&'a ProcessInfo>,
+}
+
+pub fn classify(sample: &ActivitySample) -> ActivityState {
+    let quiet_at_least = |d: Duration| sample.quiet_for.is_none_or(|quiet| quiet >= d);
+
+    if sample.ended {
+        ActivityState::Exited
+    } else if quiet_at_least(PROMPT_SETTLE) && asks_for_input(sample.recent_output) {
+        ActivityState::WaitingForInput
+    } else if !quiet_at_least(WORKING_QUIET) {
+        ActivityState::Working
+    } else {
+        match sample.job {
+            Some(job) if agent_name(job).is_none() => ActivityState::Working,
+            _ => ActivityState::Idle,
+        }
+    }
+}
+
+/// The known agent a process is, by its name or the script it runs, e.g. `node /usr/local/bin/gemini`.
+pub fn agent_name(process: &ProcessInfo) -> Option<&'static str> {
+    std::iter::once(process.name.as_str())
+        .chain(process.cmdline.iter().take(3).map(String::as_str))
+        .filter_map(|arg| arg.rsplit(['/', '\\']).next())
+        .map(|name| name.strip_suffix(".js").unwrap_or(name))
+        .find_map(|name| AGENTS.iter().copied().find(|agent| name.eq_ignore_ascii_case(agent)))
+}
+
+fn asks_for_input(output: &[u8]) -> bool {
+    let text = squash(&ansi::strip_ansi(output));
+    INPUT_PROMPTS.iter().any(|prompt| text.contains(&squash(prompt)))
+}
+
+fn squash(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str, cmdline: &[&str]) -> ProcessInfo {
+        ProcessInfo {
+            pid: 1,
+            pgid: None,
+            name: name.to_string(),
+            cmdline: cmdline.iter().map(|arg| arg.to_string()).collect(),
+            cwd: None,
+            start_time: 0,
+        }
+    }
+
+    fn sample<'a>(quiet_ms: u64, output: &'a [u8], job: Option<&'a ProcessInfo>) -> ActivitySample<'a> {
+        ActivitySample {
+            ended: false,
+            quiet_for: Some(Duration::from_millis(quiet_ms)),
+            recent_output: output,
+            job,
+        }
+    }
+
+    #[test]
+    fn ended_sessions_are_exited() {
+        let sample = ActivitySample {
+            ended: true,
+            ..sample(0, b"Do you want to proceed?", None)
+        };
+        assert_eq!(classify(&sample), ActivityState::Exited);
+    }
+
+    #[test]
+    fn recent_output_is_working() {
+        assert_eq!(classify(&sample(100, b"building...", None)), ActivityState::Working);
+    }
+
+    #[test]
+    fn settled_questions_wait_for_input() {
+        let output = b"\x1b[1mDo you\x1b[1Cwant to proceed?\x1b[0m";
+        assert_eq!(classify(&sample(600, output, None)), ActivityState::WaitingForInput);
+        // Still being drawn, or scrolled past by more output
+        assert_eq!(classify(&sample(100, output, None)), ActivityState::Working);
+    }
+
+    #[test]
+    fn quiet_sessions_depend_on_the_foreground_job() {
+        let vim = process("vim", &["vim"]);
+        let claude = process("claude", &["claude"]);
+        assert_eq!(classify(&sample(2000, b"$ ", None)), ActivityState::Idle);
+        assert_eq!(classify(&sample(2000, b"", Some(&vim))), ActivityState::Working);
+        assert_eq!(classify(&sample(2000, b"> ", Some(&claude))), ActivityState::Idle);
+    }
+
+    #[test]
+    fn no_output_yet_is_idle() {
+        let sample = ActivitySample {
+            quiet_for: None,
+            ..sample(0, b"", None)
+        };
+        assert_eq!(classify(&sample), ActivityState::Idle);
+    }
+
+    #[test]
+    fn agents_are_found_by_name_or_script() {
+        assert_eq!(agent_name(&process("claude", &["claude"])), Some("claude"));
+        assert_eq!(
+            agent_name(&process("node", &["node", "/usr/local/bin/gemini"])),
+            Some("gemini")
+        );
+        assert_eq!(
+            agent_name(&process("node", &["node", "C:\\tools\\Codex.js"])),
+            Some("codex")
+        );
+        assert_eq!(agent_name(&process("node", &["node", "server.js"])), None);
+        assert_eq!(agent_name(&process("vim", &["vim", "claude.md"])), None);
+    }
+}
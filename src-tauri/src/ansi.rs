@@ -0,0 +1,99 @@
+use crate::vt_splitter::VtState;
+
+/// Plain text of terminal output: escape sequences and control characters are
+/// removed, newlines and tabs kept.
+pub fn strip_ansi(bytes: &[u8]) -> String {
+    let mut text = Vec::with_capacity(bytes.len());
+    strip(bytes, |b, _| text.push(b));
+    String::from_utf8_lossy(&text).into_owned()
+}
+
+/// Like `strip_ansi`, plus where each byte of the text came from in `bytes`.
+pub fn strip_ansi_mapped(bytes: &[u8]) -> (String, Vec<usize>) {
+    let mut text = Vec::with_capacity(bytes.len());
+    let mut sources = Vec::with_capacity(bytes.len());
+    strip(bytes, |b, i| {
+        text.push(b);
+        sources.push(i);
+    });
+    let text = match String::from_utf8(text) {
+        Ok(text) => return (text, sources),
+        Err(e) => e.into_bytes(),
+    };
+
+    // Each invalid sequence becomes a replacement character, from where the sequence was
+    let mut lossy = String::with_capacity(text.len());
+    let mut lossy_sources = Vec::with_capacity(sources.len());
+    let mut at = 0;
+    for chunk in text.utf8_chunks() {
+        lossy.push_str(chunk.valid());
+        lossy_sources.extend_from_slice(&sources[at..at + chunk.valid().len()]);
+        at += chunk.valid().len();
+        if !chunk.invalid().is_empty() {
+            lossy.push(char::REPLACEMENT_CHARACTER);
+            lossy_sources.extend([sources[at]; 3]);
+            at += chunk.invalid().len();
+        }
+    }
+    (lossy, lossy_sources)
+}
+
+/// Walk output, handing `keep` each byte of plain text and its index. Characters
+/// are handed over once complete, and bytes that aren't valid UTF-8 as they are.
+fn strip(bytes: &[u8], mut keep: impl FnMut(u8, usize)) {
+    let mut state = VtState::default();
+    // The character being read, with where each of its bytes came from
+    let mut pending: Vec<(u8, usize)> = Vec::new();
+    for (i, &b) in bytes.iter().enumerate() {
+        // Bytes after a character cut short are read as if outside any sequence
+        let in_text = state.is_ground() || state.in_char();
+        let continues = state.in_char() && (0x80..=0xbf).contains(&b);
+        state.advance(b);
+        if !continues {
+            // A character cut short is left for the lossy conversion to replace
+            pending.drain(..).for_each(|(p, j)| keep(p, j));
+        }
+        if state.in_char() {
+            pending.push((b, i));
+        } else if state.is_ground() {
+            if continues {
+                pending.drain(..).for_each(|(p, j)| keep(p, j));
+                keep(b, i);
+            } else if in_text && matches!(b, b'\n' | b'\t' | 0x20..=0x7e | 0x80..) {
+                keep(b, i);
+            }
+        } else {
+            // The character was a C1 control that starts a sequence
+            pending.clear();
+        }
+    }
+    pending.into_iter().for_each(|(p, j)| keep(p, j));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_sequences_and_controls() {
+        let bytes = b"\x1b[1;31mred\x1b[0m\x1b]0;title\x07\tx\r\x1b(B\x1bPq#0\x1b\\y\n";
+        assert_eq!(strip_ansi(bytes), "red\txy\n");
+    }
+
+    #[test]
+    fn maps_text_back_to_output() {
+        let bytes = b"a\x1b[1mb\xffc";
+        let (text, sources) = strip_ansi_mapped(bytes);
+        assert_eq!(text, "ab\u{fffd}c");
+        assert_eq!(sources, [0, 5, 6, 6, 6, 7]);
+        assert_eq!(text, strip_ansi(bytes));
+    }
+
+    #[test]
+    fn agrees_with_the_splitter_on_sequence_ends() {
+        // BEL doesn't end a DCS or APC, but any ESC does
+        assert_eq!(strip_ansi(b"\x1bPq\x07x\x1b\\a\x1b_b\x1bxc"), "ac");
+        // C1 controls encoded as UTF-8 work like their 7-bit forms
+        assert_eq!(strip_ansi("\u{9b}1mcafé\u{9d}0;t\u{9c}!".as_bytes()), "café!");
+    }
+}
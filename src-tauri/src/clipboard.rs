@@ -0,0 +1,226 @@
+//! OSC 52 clipboard requests from programs in a session, e.g. tmux or neovim over ssh
+//! copying a selection. They are taken out of the output before it reaches the view
+//! and applied to the system clipboard as far as the session allows.
+
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine,
+};
+use crate::vt_splitter::VtState;
+use serde::{Deserialize, Serialize};
+
+/// Start of an OSC 52, in 7-bit form and with the C1 introducer encoded as UTF-8.
+const INTRODUCERS: [&[u8]; 2] = [b"\x1b]52;", b"\xc2\x9d52;"];
+/// What follows the introducer of an OSC 52.
+const CODE: &[u8] = b"52;";
+const BEL: &[u8] = b"\x07";
+const ST: &[u8] = b"\x1b\\";
+
+/// What programs in a session may do with the system clipboard. Nothing until the
+/// user allows it, e.g. when the view offers a refused request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardAccess {
+    #[default]
+    Off,
+    /// Copy only. Reading would hand the clipboard to whatever runs in the session, remote hosts included.
+    Write,
+    ReadWrite,
+}
+
+pub enum ClipboardRequest {
+    /// Put text on the clipboard
+    Set(String),
+    /// Reply with the clipboard, echoing the request's selection and terminator
+    Query {
+        selection: String,
+        terminator: &'static [u8],
+    },
+}
+
+impl ClipboardRequest {
+    pub fn is_query(&self) -> bool {
+        matches!(self, ClipboardRequest::Query { .. })
+    }
+
+    pub fn allowed(&self, access: ClipboardAccess) -> bool {
+        match self {
+            ClipboardRequest::Set(_) => access != ClipboardAccess::Off,
+            ClipboardRequest::Query { .. } => access == ClipboardAccess::ReadWrite,
+        }
+    }
+}
+
+/// Take complete OSC 52 sequences out of output. Unfinished or aborted ones are left in place,
+/// and so are ones inside another string, like tmux passthrough, which aren't meant for us.
+///
+/// `data` comes from the `VtSplitter`, so it starts outside any sequence.
+pub fn take_requests(data: Vec<u8>) -> (Vec<u8>, Vec<ClipboardRequest>) {
+    if find_introducer(&data, 0).is_none() {
+        return (data, Vec::new());
+    }
+
+    let mut rest = Vec::with_capacity(data.len());
+    let mut requests = Vec::new();
+    let mut copied = 0;
+    let mut state = VtState::default();
+    // The ESC being read came inside a DCS, SOS, PM or APC string. An OSC it starts is
+    // wrapped in that string, the way tmux passthrough doubles the ESCs it wraps.
+    let mut wrapped = false;
+    // Where the OSC being read starts and its payload begins, unless it's wrapped
+    let mut osc: Option<(usize, usize)> = None;
+    // Where an ESC that may start the OSC's ST is
+    let mut osc_escape: Option<usize> = None;
+
+    for (i, &b) in data.iter().enumerate() {
+        let previous = state;
+        state.advance(b);
+
+        let ended = if previous.in_osc() && !state.in_osc() {
+            match b {
+                0x07 => Some((i, BEL)),
+                // The C1 form of ST, encoded as UTF-8
+                0x9c if state.is_ground() => Some((i - 1, ST)),
+                _ => None,
+            }
+        } else if b == b'\\' && state.is_ground() {
+            osc_escape.map(|at| (at, ST))
+        } else {
+            None
+        };
+        let request = osc
+            .zip(ended)
+            .filter(|&((_, body), (body_end, _))| data[body..body_end].starts_with(CODE));
+        if let Some(((start, body), (body_end, terminator))) = request {
+            rest.extend_from_slice(&data[copied..start]);
+            requests.extend(parse(&data[body + CODE.len()..body_end], terminator));
+            copied = i + 1;
+        }
+
+        osc_escape = (previous.in_osc() && state.in_escape()).then_some(i);
+        if state.in_escape() {
+            wrapped = if previous.in_escape() { wrapped } else { previous.in_string() };
+        }
+        if !state.in_osc() && osc_escape.is_none() {
+            osc = None;
+        } else if state.in_osc() && !previous.in_osc() {
+            // Started by `ESC ]` or its C1 form, both two bytes long
+            osc = (!wrapped || !previous.in_escape()).then_some((i - 1, i + 1));
+        }
+    }
+    rest.extend_from_slice(&data[copied..]);
+    (rest, requests)
+}
+
+/// The answer to a query, as a terminal would send it.
+pub fn reply(selection: &str, text: &str, terminator: &[u8]) -> Vec<u8> {
+    let mut reply = format!("\x1b]52;{};{}", selection, STANDARD.encode(text)).into_bytes();
+    reply.extend_from_slice(terminator);
+    reply
+}
+
+/// The next introducer at or after `from`: where it starts and where its payload starts.
+fn find_introducer(data: &[u8], from: usize) -> Option<(usize, usize)> {
+    INTRODUCERS
+        .iter()
+        .filter_map(|intro| {
+            data[from..]
+                .windows(intro.len())
+                .position(|w| w == *intro)
+                .map(|i| (from + i, from + i + intro.len()))
+        })
+        .min()
+}
+
+/// `Pc;Pd`: the selection, and base64 text or `?` for a query.
+fn parse(body: &[u8], terminator: &'static [u8]) -> Option<ClipboardRequest> {
+    let body = std::str::from_utf8(body).ok()?;
+    let (selection, data) = body.split_once(';')?;
+    if data == "?" {
+        return Some(ClipboardRequest::Query {
+            selection: selection.to_string(),
+            terminator,
+        });
+    }
+    let data = data.trim();
+    let decoded = STANDARD.decode(data).or_else(|_| STANDARD_NO_PAD.decode(data)).ok()?;
+    Some(ClipboardRequest::Set(String::from_utf8_lossy(&decoded).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set_text(request: &ClipboardRequest) -> Option<&str> {
+        match request {
+            ClipboardRequest::Set(text) => Some(text),
+            ClipboardRequest::Query { .. } => None,
+        }
+    }
+
+    #[test]
+    fn takes_requests_out_of_output() {
+        let (rest, requests) = take_requests(b"a\x1b]52;c;aGVsbG8=\x07b\xc2\x9d52;c;d29ybGQ\x1b\\c".to_vec());
+        assert_eq!(rest, b"abc");
+        let texts: Vec<_> = requests.iter().filter_map(set_text).collect();
+        assert_eq!(texts, ["hello", "world"]);
+    }
+
+    #[test]
+    fn query_echoes_selection_and_terminator() {
+        let (rest, requests) = take_requests(b"\x1b]52;p;?\x1b\\".to_vec());
+        assert!(rest.is_empty());
+        match &requests[..] {
+            [ClipboardRequest::Query { selection, terminator }] => {
+                assert_eq!(selection, "p");
+                assert_eq!(*terminator, ST);
+            }
+            _ => panic!("expected one query"),
+        }
+        assert_eq!(reply("p", "hi", ST), b"\x1b]52;p;aGk=\x1b\\");
+    }
+
+    #[test]
+    fn leaves_unfinished_and_aborted_requests() {
+        let unfinished = b"\x1b]52;c;aGVsbG8=".to_vec();
+        let (rest, requests) = take_requests(unfinished.clone());
+        assert_eq!(rest, unfinished);
+        assert!(requests.is_empty());
+
+        let aborted = b"\x1b]52;c;aGVs\x18bG8=\x07".to_vec();
+        let (rest, requests) = take_requests(aborted.clone());
+        assert_eq!(rest, aborted);
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn ignores_requests_inside_passthrough() {
+        // tmux wraps what it passes through in a DCS, doubling the ESCs inside
+        let wrapped = b"\x1bPtmux;\x1b\x1b]52;c;aGVsbG8=\x07\x1b\\x".to_vec();
+        let (rest, requests) = take_requests(wrapped.clone());
+        assert_eq!(rest, wrapped);
+        assert!(requests.is_empty());
+
+        // Once the string ends, requests count again
+        let after = b"\x1bPq#0\x1b\\\x1b]52;c;aGk=\x07".to_vec();
+        let (rest, requests) = take_requests(after);
+        assert_eq!(rest, b"\x1bPq#0\x1b\\");
+        assert_eq!(requests.iter().filter_map(set_text).collect::<Vec<_>>(), ["hi"]);
+    }
+
+    #[test]
+    fn access_is_off_until_allowed() {
+        let set = ClipboardRequest::Set(String::new());
+        let query = ClipboardRequest::Query { selection: "c".to_string(), terminator: BEL };
+        assert!(!set.allowed(ClipboardAccess::default()));
+        assert!(set.allowed(ClipboardAccess::Write) && !query.allowed(ClipboardAccess::Write));
+        assert!(query.allowed(ClipboardAccess::ReadWrite));
+    }
+
+    #[test]
+    fn takes_requests_in_c1_form() {
+        let (rest, requests) = take_requests("a\u{9d}52;c;aGk=\u{9c}b".as_bytes().to_vec());
+        assert_eq!(rest, b"ab");
+        assert_eq!(requests.iter().filter_map(set_text).collect::<Vec<_>>(), ["hi"]);
+    }
+}
@@ -0,0 +1,155 @@
+//! `path:line[:col]` references in output, like `src/foo.rs:42:7` in compiler errors,
+//! test failures and agent output, resolved against the session's directories so the
+//! view can open them in the editor. Only references to files that exist are kept.
+
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+#[cfg(target_os = "macos")]
+use std::process::Command;
+
+/// A path, with an extension or at least one directory, then `:line` and maybe `:col`.
+/// It has to start the line or follow a space, quote or bracket, so URLs and `a=b:1` don't count.
+const LINK_PATTERN: &str = r#"(?:^|[\s'"`(\[<{=,])(?P<path>(?:~/|/|\.{1,2}/)?(?:[\w.@+-]+/)*[\w@+-][\w.@+-]*\.[A-Za-z]\w*|(?:~/|/|\.{1,2}/)?(?:[\w.@+-]+/)+[\w@+-][\w.@+-]*):(?P<line>\d+)(?::(?P<col>\d+))?"#;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FileLink {
+    /// The reference as it appears in the line
+    pub text: String,
+    /// Where it is in the line's plain text, in characters
+    pub start: usize,
+    pub end: usize,
+    /// The file it resolved to
+    pub path: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+fn link_regex() -> &'static Regex {
+    static REGEX: OnceLock<Regex> = OnceLock::new();
+    REGEX.get_or_init(|| Regex::new(LINK_PATTERN).expect("valid file link pattern"))
+}
+
+/// A regular file, not a symlink or directory.
+pub fn is_real_file(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|meta| meta.is_file() && !meta.file_type().is_symlink())
+}
+
+/// The file a reference points to: absolute, under the home dir, or relative to one of `dirs`.
+pub fn resolve(path: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let candidates: Vec<PathBuf> = if let Some(rest) = path.strip_prefix("~/") {
+        dirs::home_dir().map(|home| home.join(rest)).into_iter().collect()
+    } else if Path::new(path).is_absolute() {
+        vec![PathBuf::from(path)]
+    } else {
+        dirs.iter().map(|dir| dir.join(path)).collect()
+    };
+    candidates
+        .into_iter()
+        // Drops the `./` a joined relative path may carry
+        .map(|candidate| candidate.components().collect::<PathBuf>())
+        .find(|candidate| is_real_file(candidate))
+}
+
+/// File references in a line of plain text that resolve to files in `dirs`.
+pub fn find_links(text: &str, dirs: &[PathBuf]) -> Vec<FileLink> {
+    if !text.contains(':') {
+        return Vec::new();
+    }
+    link_regex()
+        .captures_iter(text)
+        .filter_map(|captures| {
+            let path = captures.name("path")?;
+            let whole = captures.get(0)?;
+            let line: u32 = captures["line"].parse().ok().filter(|&line| line > 0)?;
+            let column = captures.name("col").and_then(|col| col.as_str().parse().ok());
+            let resolved = resolve(path.as_str(), dirs)?;
+            Some(FileLink {
+                text: text[path.start()..whole.end()].to_string(),
+                start: text[..path.start()].chars().count(),
+                end: text[..whole.end()].chars().count(),
+                path: resolved.to_string_lossy().into_owned(),
+                line,
+                column,
+            })
+        })
+        .collect()
+}
+
+/// Escape a path for a URL, leaving `/` and unreserved characters as they are.
+#[cfg(any(target_os = "macos", test))]
+fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Open a file in Zed at a line, and column if given, through its URL scheme.
+#[cfg(target_os = "macos")]
+pub fn open_in_editor(path: &Path, line: u32, column: Option<u32>) -> Result<(), String> {
+    let mut target = format!("zed://file{}:{}", percent_encode_path(&path.to_string_lossy()), line);
+    if let Some(column) = column {
+        target.push_str(&format!(":{}", column));
+    }
+    Command::new("open")
+        .arg(&target)
+        .output()
+        .map_err(|e| format!("Failed to open in Zed: {}", e))?;
+    Ok(())
+}
+
+/// Open a file in Zed at a line, and column if given, through its command line tool.
+#[cfg(not(target_os = "macos"))]
+pub fn open_in_editor(path: &Path, line: u32, column: Option<u32>) -> Result<(), String> {
+    let mut target = format!("{}:{}", path.display(), line);
+    if let Some(column) = column {
+        target.push_str(&format!(":{}", column));
+    }
+    crate::shell_env::command("zed")
+        .arg(&target)
+        .output()
+        .map_err(|e| format!("Failed to open in Zed: {}", e))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_paths_for_urls() {
+        assert_eq!(percent_encode_path("/Users/me/src/main.rs"), "/Users/me/src/main.rs");
+        assert_eq!(percent_encode_path("/tmp/My Project/a#b?.rs"), "/tmp/My%20Project/a%23b%3F.rs");
+        assert_eq!(percent_encode_path("/tmp/ü.rs"), "/tmp/%C3%BC.rs");
+    }
+
+    #[test]
+    fn finds_references_to_existing_files() {
+        let dir = std::env::temp_dir().join(format!("file-links-test-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::write(dir.join("src/foo.rs"), "").unwrap();
+        let dirs = [dir.clone()];
+
+        let links = find_links("error: --> src/foo.rs:42:7 and src/missing.rs:3", &dirs);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].text, "src/foo.rs:42:7");
+        assert_eq!((links[0].start, links[0].end), (11, 26));
+        assert_eq!(links[0].path, dir.join("src/foo.rs").to_string_lossy());
+        assert_eq!((links[0].line, links[0].column), (42, Some(7)));
+
+        // URLs, times and zero lines aren't references
+        assert!(find_links("http://src/foo.rs:80 12:30 src/foo.rs:0", &dirs).is_empty());
+        assert_eq!(resolve("./src/foo.rs", &dirs), Some(dir.join("src/foo.rs")));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
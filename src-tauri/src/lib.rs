@@ -1,245 +1,753 @@
-use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
+#[cfg(unix)]
+mod pty_host;
+mod activity;
+mod ansi;
+mod clipboard;
+mod file_links;
+mod notifications;
+mod osc;
+mod output_log;
+mod process_info;
+mod pty_output;
+mod pty_script;
+mod recording;
+mod screen;
+mod scrollback;
+mod search;
+mod shell_env;
+mod signals;
+mod shell_integration;
+mod shell_profiles;
+mod shutdown;
+mod terminal_state;
+mod triggers;
+mod vt_splitter;
+
+use portable_pty::{native_pty_system, Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     io::{Read, Write},
-    sync::Arc,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{SyncSender, TrySendError},
+        Arc,
+    },
     thread,
     process::Command,
-    path::Path,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tauri::{async_runtime::Mutex as AsyncMutex, State, AppHandle, Emitter};
+use tauri::{async_runtime::Mutex as AsyncMutex, ipc::{Channel, InvokeResponseBody}, AppHandle, Emitter, Manager, State};
 use sysinfo::{System, Components, Networks};
-
+use tauri_plugin_clipboard_manager::ClipboardExt;
+use activity::{ActivitySample, ActivityState};
+use clipboard::{ClipboardAccess, ClipboardRequest};
+use file_links::FileLink;
+use notifications::{NotificationKind, NotificationSettings, Notifier};
+use output_log::{LogConfig, LogFileInfo, LogWriter, SessionLog};
+use process_info::ProcessInfo;
+use pty_script::{ScriptResult, ScriptSession, ScriptStep};
+use recording::{Playbacks, RecordingInfo, Recorder, SessionRecording};
+use pty_output::{FlowConfig, OutputStats, SessionOutput, INPUT_QUEUE_CHUNKS, OUTPUT_QUEUE_CHUNKS};
+use screen::{ScreenSnapshot, TerminalScreen};
+use scrollback::DEFAULT_SCROLLBACK_BYTES;
+use search::{SearchResults, SearchTarget};
+use shell_profiles::{PtyCommand, ShellProfile};
+use shutdown::{ShutdownReport, SHUTDOWN_GRACE};
+use signals::PtySignal;
+use terminal_state::{CommandRecord, CwdSource, TerminalEvent, TerminalState};
+use triggers::{LineSplitter, OutputLine, TriggerMatch, TriggerRule, Triggers};
+use vt_splitter::VtSplitter;
+
+#[cfg(unix)]
+pub use pty_host::{run as run_pty_host, HOST_ARG as PTY_HOST_ARG};
+
+/// Sessions are shared as `Arc`s so commands only hold the session map for the lookup;
+/// the master has its own lock and input goes through a queue.
 struct PtySession {
-    master: Box<dyn MasterPty + Send>,
-    writer: Box<dyn Write + Send>,
-    child: Box<dyn Child + Send + Sync>,
+    master: std::sync::Mutex<Box<dyn MasterPty + Send>>,
+    /// The PTY host and the session's id there, if the host owns the session
+    #[cfg(unix)]
+    host: Option<(pty_host::HostClient, u32)>,
+    /// Input for the session's writer thread, so a PTY that stopped reading
+    /// only ever blocks that thread
+    input: SyncSender<Vec<u8>>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
     child_pid: u32,
-    _reader_thread: thread::JoinHandle<()>,
+    /// Directory the session was started in
+    cwd: Option<String>,
+    /// Seconds since the Unix epoch
+    created_at: u64,
+    output: Arc<SessionOutput>,
+    terminal: Arc<std::sync::Mutex<TerminalState>>,
+    screen: Arc<std::sync::Mutex<TerminalScreen>>,
+    /// What OSC 52 requests from the session may do
+    clipboard: Arc<std::sync::Mutex<ClipboardAccess>>,
+    recording: Arc<SessionRecording>,
+    log: Arc<SessionLog>,
+    /// Taken by the shutdown that joins them
+    threads: std::sync::Mutex<Option<SessionThreads>>,
 }
 
-pub struct PtyState {
-    sessions: Arc<AsyncMutex<HashMap<u32, PtySession>>>,
-    next_id: Arc<AsyncMutex<u32>>,
+struct SessionThreads {
+    reader: thread::JoinHandle<()>,
+    forward: thread::JoinHandle<()>,
+    wait: thread::JoinHandle<()>,
 }
 
-impl Default for PtyState {
-    fn default() -> Self {
-        Self {
-            sessions: Arc::new(AsyncMutex::new(HashMap::new())),
-            next_id: Arc::new(AsyncMutex::new(1)),
-        }
+impl PtySession {
+    /// Process group in the foreground of the session's terminal: tcgetpgrp on
+    /// the master, or the PTY host's answer for it. Blocks while the host answers.
+    #[cfg(unix)]
+    fn foreground_pgid(&self) -> Option<u32> {
+        let pgid = match &self.host {
+            // Not through the master, so a slow host doesn't hold up resizes meanwhile
+            Some((host, id)) => host.foreground_pgid(*id),
+            None => self.master.lock().unwrap().process_group_leader(),
+        };
+        pgid.filter(|&pgid| pgid > 0).map(|pgid| pgid as u32)
+    }
+
+    #[cfg(not(unix))]
+    fn foreground_pgid(&self) -> Option<u32> {
+        None
     }
 }
 
-/// Find the safe boundary to emit, excluding incomplete escape sequences at the end.
-/// Returns the byte index up to which it's safe to emit.
-fn find_safe_emit_boundary(text: &str) -> usize {
-    let bytes = text.as_bytes();
-    let len = bytes.len();
+impl ScriptSession for PtySession {
+    fn send(&self, data: &[u8]) -> Result<(), String> {
+        write_input(self, data)
+    }
 
-    if len == 0 {
-        return 0;
+    fn output_since(&self, from: u64) -> (Vec<u8>, u64) {
+        let output = self.output.lock();
+        let scrollback = &output.scrollback;
+        if from >= scrollback.end_offset() {
+            return (Vec::new(), from);
+        }
+        let start = from.max(scrollback.start_offset());
+        (scrollback.tail(start, usize::MAX), start)
     }
 
-    // Look for ESC (0x1b) in the last 64 bytes (escape sequences are typically short)
-    let search_start = len.saturating_sub(64);
+    fn output_end(&self) -> u64 {
+        let end = self.output.lock().scrollback.end_offset();
+        end.max(self.terminal.lock().unwrap().observed_offset())
+    }
 
-    // Find the last ESC character
-    let mut last_esc = None;
-    for i in (search_start..len).rev() {
-        if bytes[i] == 0x1b {
-            last_esc = Some(i);
-            break;
-        }
+    fn input_offset(&self) -> u64 {
+        self.terminal.lock().unwrap().input_offset()
     }
 
-    let esc_pos = match last_esc {
-        Some(pos) => pos,
-        None => return len, // No ESC found, safe to emit all
-    };
+    fn prompt_ready_at(&self) -> Option<u64> {
+        self.terminal.lock().unwrap().prompt_ready_at()
+    }
+
+    fn prompt_start_at(&self) -> Option<u64> {
+        self.terminal.lock().unwrap().prompt_start_at()
+    }
+
+    fn marks_input(&self) -> bool {
+        self.terminal.lock().unwrap().marks_input()
+    }
+
+    fn shell_integration(&self) -> bool {
+        self.terminal.lock().unwrap().shell_integration
+    }
 
-    // Check if the escape sequence starting at esc_pos is complete
-    let seq = &bytes[esc_pos..];
+    fn quiet_for(&self) -> Option<Duration> {
+        self.terminal.lock().unwrap().quiet_for()
+    }
 
-    if is_escape_sequence_complete(seq) {
-        return len; // Complete sequence, safe to emit all
+    fn ended(&self) -> bool {
+        self.output.lock().ended
     }
+}
+
+/// Settings a session starts with, whether it was just spawned or adopted from the PTY host.
+struct SessionOptions {
+    cwd: Option<String>,
+    scrollback_limit: usize,
+    flow: FlowConfig,
+    started_at: SystemTime,
+    /// Output the PTY host replays from its buffer before any new output
+    replay_bytes: u64,
+}
 
-    // Incomplete sequence, emit up to the ESC
-    esc_pos
+/// How a session's child process ended. Sent with the end message on the
+/// output channel, and kept for a while after that for `get_pty_exit_status`.
+#[derive(Clone, serde::Serialize)]
+struct PtyExit {
+    code: u32,
+    /// Name of the terminating signal, e.g. "Killed"
+    signal: Option<String>,
+    success: bool,
+    runtime_ms: u64,
 }
 
-/// Check if an escape sequence is complete.
-/// Returns true if the sequence is complete or not a recognized escape sequence.
-fn is_escape_sequence_complete(seq: &[u8]) -> bool {
-    if seq.is_empty() || seq[0] != 0x1b {
-        return true;
+/// Exit records outlive their sessions by this long.
+const EXIT_RECORD_TTL: Duration = Duration::from_secs(10 * 60);
+
+impl PtyExit {
+    fn new(status: &ExitStatus, runtime: Duration) -> Self {
+        Self {
+            code: status.exit_code(),
+            signal: exit_signal(status),
+            success: status.success(),
+            runtime_ms: runtime.as_millis() as u64,
+        }
     }
+}
+
+#[derive(Clone, serde::Serialize)]
+struct PtyCwdChanged {
+    session_id: u32,
+    cwd: String,
+    source: CwdSource,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct PtyCommandEvent {
+    session_id: u32,
+    command: CommandRecord,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct PtyStateChanged {
+    session_id: u32,
+    state: ActivityState,
+    previous: ActivityState,
+    /// Agent in the foreground, e.g. "claude"
+    agent: Option<&'static str>,
+}
+
+fn emit_terminal_event(app: &AppHandle, session_id: u32, event: TerminalEvent) {
+    notify_terminal_event(app, session_id, &event);
+    let _ = match event {
+        TerminalEvent::CwdChanged { cwd, source } => {
+            app.emit("pty-cwd-changed", PtyCwdChanged { session_id, cwd, source })
+        }
+        TerminalEvent::CommandStarted(command) => {
+            app.emit("pty-command-started", PtyCommandEvent { session_id, command })
+        }
+        TerminalEvent::CommandFinished(command) => {
+            app.emit("pty-command-finished", PtyCommandEvent { session_id, command })
+        }
+        TerminalEvent::ActivityChanged { state, previous, agent } => app.emit(
+            "pty-state-changed",
+            PtyStateChanged { session_id, state, previous, agent },
+        ),
+        TerminalEvent::Bell => Ok(()),
+    };
+}
 
-    if seq.len() == 1 {
-        return false; // Just ESC, incomplete
+/// Raise a desktop notification for the events that warrant one.
+fn notify_terminal_event(app: &AppHandle, session_id: u32, event: &TerminalEvent) {
+    let notifier = app.state::<Notifier>();
+    match event {
+        TerminalEvent::Bell => notifier.notify(app, session_id, NotificationKind::Bell, "Bell"),
+        TerminalEvent::CommandFinished(command) => {
+            let runtime = Duration::from_millis(command.duration_ms.unwrap_or(0));
+            if runtime < notifier.long_command() {
+                return;
+            }
+            let outcome = match command.exit_code {
+                Some(code) if code != 0 => format!("failed with exit code {}", code),
+                _ => "finished".to_string(),
+            };
+            let body = format!(
+                "{} {} after {}s",
+                command.command.as_deref().unwrap_or("Command"),
+                outcome,
+                runtime.as_secs()
+            );
+            notifier.notify(app, session_id, NotificationKind::CommandFinished, &body);
+        }
+        TerminalEvent::ActivityChanged {
+            state,
+            previous: ActivityState::Working,
+            agent: Some(agent),
+        } => {
+            let body = match state {
+                ActivityState::Idle => format!("{} finished", agent),
+                ActivityState::WaitingForInput => format!("{} is waiting for input", agent),
+                _ => return,
+            };
+            notifier.notify(app, session_id, NotificationKind::AgentIdle, &body);
+        }
+        _ => {}
     }
+}
 
-    match seq[1] {
-        // CSI sequence: ESC [ ... (ends with 0x40-0x7E)
-        b'[' => {
-            if seq.len() == 2 {
-                return false; // Just ESC [, incomplete
+#[derive(Clone, serde::Serialize)]
+struct PtyTriggerMatched {
+    session_id: u32,
+    #[serde(flatten)]
+    matched: TriggerMatch,
+}
+
+/// Check lines of new output against the user's trigger rules, emitting `pty-trigger-matched`
+/// for every match and notifying or keeping a marker where the rule asks for it.
+fn run_triggers(
+    app: &AppHandle,
+    session_id: u32,
+    terminal: &std::sync::Mutex<TerminalState>,
+    lines: impl FnOnce() -> Vec<OutputLine>,
+) {
+    let triggers = app.state::<Triggers>();
+    if triggers.is_empty() {
+        return;
+    }
+    let notifier = app.state::<Notifier>();
+    let issue_key = notifier.issue_key(session_id);
+
+    for line in lines() {
+        for matched in triggers.matches(issue_key.as_deref(), &line) {
+            if matched.notify {
+                let body = format!("{}: {}", matched.rule, matched.text);
+                notifier.notify(app, session_id, NotificationKind::Trigger, &body);
             }
-            // Look for terminating character (0x40-0x7E: @A-Z[\]^_`a-z{|}~)
-            for &b in &seq[2..] {
-                if (0x40..=0x7E).contains(&b) {
-                    return true; // Found terminator
-                }
+            if matched.mark {
+                terminal.lock().unwrap().add_marker(matched.clone());
             }
-            false // No terminator found
+            let _ = app.emit("pty-trigger-matched", PtyTriggerMatched { session_id, matched });
         }
-        // OSC sequence: ESC ] ... (ends with BEL or ST)
-        b']' => {
-            for i in 2..seq.len() {
-                if seq[i] == 0x07 {
-                    return true; // BEL terminator
-                }
-                if seq[i] == 0x1b && i + 1 < seq.len() && seq[i + 1] == b'\\' {
-                    return true; // ST terminator (ESC \)
-                }
-            }
-            false
+    }
+}
+
+/// Directories file references are resolved against: the shell's current
+/// directory, then the one the session started in, usually its worktree.
+fn link_dirs(terminal: &std::sync::Mutex<TerminalState>, initial_cwd: Option<&str>) -> Vec<PathBuf> {
+    let cwd = terminal.lock().unwrap().cwd.clone();
+    let mut dirs: Vec<PathBuf> = cwd.into_iter().map(PathBuf::from).collect();
+    if let Some(initial) = initial_cwd.map(PathBuf::from).filter(|dir| !dirs.contains(dir)) {
+        dirs.push(initial);
+    }
+    dirs
+}
+
+#[derive(Clone, serde::Serialize)]
+struct PtyClipboardRequest {
+    session_id: u32,
+    /// The program asked for the clipboard rather than setting it
+    query: bool,
+    allowed: bool,
+}
+
+/// Apply an OSC 52 request to the system clipboard if the session allows it, answering
+/// queries through the PTY. Emits `pty-clipboard-request` either way, so a refused one can be offered.
+fn handle_clipboard_request(
+    app: &AppHandle,
+    session_id: u32,
+    access: &std::sync::Mutex<ClipboardAccess>,
+    input: &SyncSender<Vec<u8>>,
+    request: ClipboardRequest,
+) {
+    let allowed = request.allowed(*access.lock().unwrap());
+    let event = PtyClipboardRequest {
+        session_id,
+        query: request.is_query(),
+        allowed,
+    };
+    let _ = app.emit("pty-clipboard-request", event);
+    if !allowed {
+        return;
+    }
+
+    match request {
+        ClipboardRequest::Set(text) => {
+            let _ = app.clipboard().write_text(text);
         }
-        // DCS sequence: ESC P ... (ends with ST)
-        b'P' => {
-            for i in 2..seq.len() {
-                if seq[i] == 0x1b && i + 1 < seq.len() && seq[i + 1] == b'\\' {
-                    return true; // ST terminator
-                }
-            }
-            false
+        ClipboardRequest::Query { selection, terminator } => {
+            let text = app.clipboard().read_text().unwrap_or_default();
+            let _ = input.try_send(clipboard::reply(&selection, &text, terminator));
+        }
+    }
+}
+
+/// portable-pty only exposes the terminating signal through its Display impl.
+fn exit_signal(status: &ExitStatus) -> Option<String> {
+    status
+        .to_string()
+        .strip_prefix("Terminated by ")
+        .map(str::to_string)
+}
+
+#[derive(serde::Serialize)]
+struct PtyScrollback {
+    data: String,
+    start_offset: u64,
+    end_offset: u64,
+}
+
+
+/// Master side and child process of a spawned PTY.
+type PtyProcess = (Box<dyn MasterPty + Send>, Box<dyn Child + Send + Sync>);
+
+pub struct PtyState {
+    sessions: Arc<AsyncMutex<HashMap<u32, Arc<PtySession>>>>,
+    next_id: AtomicU32,
+    exits: Arc<std::sync::Mutex<HashMap<u32, (PtyExit, Instant)>>>,
+    /// Named sets of sessions that input can be sent to at once, e.g. every worktree of a project
+    groups: Arc<std::sync::Mutex<HashMap<String, Vec<u32>>>>,
+    /// Reports of recent shutdowns, oldest first
+    shutdowns: Arc<std::sync::Mutex<VecDeque<ShutdownReport>>>,
+    #[cfg(unix)]
+    host: OnceLock<Option<pty_host::HostClient>>,
+    /// Held while a host session is adopted, so concurrent attaches adopt it only once
+    #[cfg(unix)]
+    adopting: AsyncMutex<()>,
+}
+
+impl Default for PtyState {
+    fn default() -> Self {
+        Self {
+            sessions: Arc::new(AsyncMutex::new(HashMap::new())),
+            next_id: AtomicU32::new(1),
+            exits: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            groups: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            shutdowns: Arc::new(std::sync::Mutex::new(VecDeque::new())),
+            #[cfg(unix)]
+            host: OnceLock::new(),
+            #[cfg(unix)]
+            adopting: AsyncMutex::new(()),
+        }
+    }
+}
+
+impl PtyState {
+    /// Look up a session. The map is only locked for the lookup itself.
+    async fn session(&self, session_id: u32) -> Result<Arc<PtySession>, String> {
+        self.sessions
+            .lock()
+            .await
+            .get(&session_id)
+            .cloned()
+            .ok_or_else(|| "Session not found".to_string())
+    }
+
+    /// Client for the background PTY host, started on first use.
+    /// `None` if the host can't be reached; sessions then stay in-process.
+    #[cfg(unix)]
+    async fn host(&self) -> Option<&pty_host::HostClient> {
+        if self.host.get().is_none() {
+            let host = tauri::async_runtime::spawn_blocking(|| pty_host::HostClient::connect_or_spawn().ok())
+                .await
+                .ok()
+                .flatten();
+            let _ = self.host.set(host);
         }
-        // Single character sequences (complete with just one char after ESC)
-        b'7' | b'8' | b'=' | b'>' | b'c' | b'D' | b'E' | b'H' | b'M' | b'N' | b'O' | b'Z' => true,
-        // SS2/SS3: ESC N/O followed by one character
-        // Already covered above as single char
-        // Unknown or other sequences - assume complete to avoid blocking
-        _ => true,
+        self.started_host()
+    }
+
+    /// Client for the PTY host, if a call to `host` has already reached it.
+    #[cfg(unix)]
+    fn started_host(&self) -> Option<&pty_host::HostClient> {
+        self.host.get().and_then(Option::as_ref)
+    }
+
+    /// Whether sessions live in the PTY host. Either all of them do or none do.
+    #[cfg(unix)]
+    fn hosted(&self) -> bool {
+        self.started_host().is_some()
+    }
+
+    #[cfg(not(unix))]
+    fn hosted(&self) -> bool {
+        false
     }
 }
 
+#[derive(serde::Serialize)]
+struct PtySessionSummary {
+    session_id: u32,
+    pid: u32,
+    cwd: Option<String>,
+    /// Seconds since the Unix epoch
+    created_at: u64,
+    /// Whether a frontend is receiving the session's output
+    attached: bool,
+    exited: bool,
+    /// The issue the session was opened for, while this app instance knows it
+    issue_key: Option<String>,
+}
+
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 async fn create_pty_session(
     app: AppHandle,
     state: State<'_, PtyState>,
     rows: u16,
     cols: u16,
     cwd: Option<String>,
+    scrollback_limit: Option<usize>,
+    flow_control: Option<FlowConfig>,
+    command: Option<PtyCommand>,
 ) -> Result<u32, String> {
-    let pty_system = native_pty_system();
+    let cmd = command.unwrap_or_default().build(cwd.as_deref())?;
 
-    let pair = pty_system
-        .openpty(PtySize {
-            rows,
-            cols,
-            pixel_width: 0,
-            pixel_height: 0,
-        })
-        .map_err(|e| format!("Failed to open pty: {}", e))?;
+    // Don't hand out ids of sessions the PTY host kept from a previous run
+    #[cfg(unix)]
+    if let Some(host) = state.host().await {
+        state.next_id.fetch_max(host.first_free_id(), Ordering::Relaxed);
+    }
+    let session_id = state.next_id.fetch_add(1, Ordering::Relaxed);
 
-    let mut cmd = CommandBuilder::new_default_prog();
-    if let Some(dir) = cwd {
-        cmd.cwd(dir);
+    let size = PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+    let scrollback_limit = scrollback_limit.unwrap_or(DEFAULT_SCROLLBACK_BYTES);
+    let (master, child) = tauri::async_runtime::spawn_blocking({
+        let app = app.clone();
+        move || spawn_pty(&app.state::<PtyState>(), session_id, size, cmd, scrollback_limit)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))??;
+
+    let options = SessionOptions {
+        cwd,
+        scrollback_limit,
+        flow: flow_control.unwrap_or_default(),
+        started_at: SystemTime::now(),
+        replay_bytes: 0,
+    };
+    start_session(&app, &state, session_id, master, child, options).await?;
+    Ok(session_id)
+}
+
+/// Spawn `cmd` in a new PTY. Unix sessions live in the PTY host once `PtyState::host` has
+/// reached it, so they survive app restarts; otherwise they live in this process. Blocks.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn spawn_pty(
+    state: &PtyState,
+    session_id: u32,
+    size: PtySize,
+    cmd: CommandBuilder,
+    scrollback_limit: usize,
+) -> Result<PtyProcess, String> {
+    #[cfg(unix)]
+    if let Some(host) = state.started_host() {
+        host.spawn_session(session_id, size, &cmd, scrollback_limit)?;
+        return host.open_session(session_id).map(|(_, process)| process).inspect_err(|_| {
+            // Nobody could ever attach to it, so don't leave it running in the host
+            let _ = host.shutdown_session(session_id, SHUTDOWN_GRACE);
+        });
     }
-    // Set TERM for proper escape sequence handling
-    cmd.env("TERM", "xterm-256color");
-    // Set UTF-8 locale for proper Korean/CJK character handling
-    // DMG-installed apps don't inherit shell environment variables
-    cmd.env("LANG", "en_US.UTF-8");
-    cmd.env("LC_ALL", "en_US.UTF-8");
+
+    let pair = native_pty_system()
+        .openpty(size)
+        .map_err(|e| format!("Failed to open pty: {}", e))?;
 
     let child = pair.slave.spawn_command(cmd).map_err(|e| format!("Failed to spawn command: {}", e))?;
 
     // Drop slave - we only need master
     drop(pair.slave);
 
-    let writer = pair.master.take_writer().map_err(|e| format!("Failed to get writer: {}", e))?;
-    let mut reader = pair.master.try_clone_reader().map_err(|e| format!("Failed to get reader: {}", e))?;
+    Ok((pair.master, child))
+}
 
-    let mut next_id = state.next_id.lock().await;
-    let session_id = *next_id;
-    *next_id += 1;
+/// Start reading a spawned PTY and register it as `session_id`.
+async fn start_session(
+    app: &AppHandle,
+    state: &PtyState,
+    session_id: u32,
+    master: Box<dyn MasterPty + Send>,
+    mut child: Box<dyn Child + Send + Sync>,
+    options: SessionOptions,
+) -> Result<(), String> {
+    let mut writer = master.take_writer().map_err(|e| format!("Failed to get writer: {}", e))?;
+    let mut reader = master.try_clone_reader().map_err(|e| format!("Failed to get reader: {}", e))?;
+
+    let output = Arc::new(SessionOutput::new(options.scrollback_limit, options.flow));
+    let terminal = Arc::new(std::sync::Mutex::new(TerminalState::new(options.cwd.clone())));
+    let size = master.get_size().unwrap_or_default();
+    let screen = Arc::new(std::sync::Mutex::new(TerminalScreen::new(size.rows, size.cols)));
+    let clipboard = Arc::new(std::sync::Mutex::new(ClipboardAccess::default()));
+    let recording = Arc::new(SessionRecording::default());
+    let log = Arc::new(SessionLog::default());
+
+    // Write input on its own thread; a child that stops reading fills the queue, not the app
+    let (input, input_rx) = std::sync::mpsc::sync_channel::<Vec<u8>>(INPUT_QUEUE_CHUNKS);
+    thread::spawn(move || {
+        for data in input_rx {
+            if writer.write_all(&data).and_then(|_| writer.flush()).is_err() {
+                break;
+            }
+        }
+    });
 
-    // Spawn thread to read from PTY and emit events
-    let app_clone = app.clone();
+    // Batch output and send it to the frontend at the pace it acknowledges
+    let (chunk_tx, chunk_rx) = std::sync::mpsc::sync_channel::<Vec<u8>>(OUTPUT_QUEUE_CHUNKS);
+    let forward_output = output.clone();
+    let forward_thread = thread::spawn(move || pty_output::forward(&forward_output, chunk_rx));
+
+    // Spawn thread to read from PTY and forward output
+    let reader_app = app.clone();
+    let reader_terminal = terminal.clone();
+    let reader_screen = screen.clone();
+    let reader_output = output.clone();
+    let reader_clipboard = clipboard.clone();
+    let reader_input = input.clone();
+    let reader_recording = recording.clone();
+    let reader_log = log.clone();
+    let replay_bytes = options.replay_bytes;
     let reader_thread = thread::spawn(move || {
         let mut buf = [0u8; 8192];
-        let mut pending: Vec<u8> = Vec::new(); // Buffer for incomplete UTF-8 sequences
+        let mut splitter = VtSplitter::new(options.flow.max_sequence_bytes);
+        let mut stream_offset: u64 = 0;
+        let mut read_offset: u64 = 0;
+        let mut lines = LineSplitter::new();
+
+        // Blocks while the forwarder is backed up, so the PTY isn't read any faster
+        let emit_output = |data: &[u8]| {
+            let _ = chunk_tx.send(data.to_vec());
+        };
 
         loop {
-            match reader.read(&mut buf) {
-                Ok(0) => {
-                    // Flush any remaining pending bytes (as lossy)
-                    if !pending.is_empty() {
-                        let text = String::from_utf8_lossy(&pending).into_owned();
-                        let _ = app_clone.emit(&format!("pty-output-{}", session_id), text);
-                    }
-                    let _ = app_clone.emit(&format!("pty-end-{}", session_id), ());
-                    break;
-                }
+            // Only whole escape sequences and characters go on, so nothing downstream sees half of one
+            let (data, done) = match reader.read(&mut buf) {
+                Ok(0) | Err(_) => (splitter.finish(), true),
                 Ok(n) => {
-                    pending.extend_from_slice(&buf[..n]);
-
-                    // Find valid UTF-8 boundary
-                    let valid_up_to = match std::str::from_utf8(&pending) {
-                        Ok(_) => pending.len(),
-                        Err(e) => e.valid_up_to(),
-                    };
-
-                    if valid_up_to > 0 {
-                        // Safe: we just validated this range is valid UTF-8
-                        let text = unsafe {
-                            std::str::from_utf8_unchecked(&pending[..valid_up_to])
-                        };
-
-                        // Find incomplete escape sequence at the end
-                        // ESC = 0x1b, look for unterminated sequences
-                        let emit_up_to = find_safe_emit_boundary(text);
-
-                        if emit_up_to > 0 {
-                            let to_emit = text[..emit_up_to].to_owned();
-                            let _ = app_clone.emit(&format!("pty-output-{}", session_id), to_emit);
-                            pending.drain(..emit_up_to);
-                        }
+                    splitter.set_max_sequence_bytes(reader_output.lock().flow.max_sequence_bytes);
+                    (splitter.split(&buf[..n]), false)
+                }
+            };
+
+            if !data.is_empty() {
+                // Output the PTY host replays was acted on when it first arrived: clipboard
+                // requests, events, triggers and the log. It only rebuilds the session's state.
+                let replayed = read_offset < replay_bytes;
+                read_offset += data.len() as u64;
+
+                // Clipboard requests are handled here and never reach the view or the scrollback
+                let (data, requests) = clipboard::take_requests(data);
+                if !replayed {
+                    for request in requests {
+                        handle_clipboard_request(&reader_app, session_id, &reader_clipboard, &reader_input, request);
                     }
+                }
 
-                    // Keep incomplete sequence in pending (max 4 bytes for UTF-8 + reasonable escape seq)
-                    // If pending grows too large, it's corrupted data
-                    if pending.len() > 128 {
-                        // Force flush as lossy to prevent memory buildup
-                        let text = String::from_utf8_lossy(&pending).into_owned();
-                        let _ = app_clone.emit(&format!("pty-output-{}", session_id), text);
-                        pending.clear();
+                reader_screen.lock().unwrap().process(&data);
+
+                // Shell integration markers and directory reports
+                let events = reader_terminal.lock().unwrap().observe(&data, stream_offset);
+                if !replayed {
+                    for event in events {
+                        emit_terminal_event(&reader_app, session_id, event);
                     }
+                    run_triggers(&reader_app, session_id, &reader_terminal, || lines.feed(&data, stream_offset));
                 }
-                Err(_) => {
-                    let _ = app_clone.emit(&format!("pty-end-{}", session_id), ());
-                    break;
+                stream_offset += data.len() as u64;
+
+                if !data.is_empty() {
+                    reader_recording.output(&data);
+                    if !replayed {
+                        reader_log.write(&data);
+                    }
+                    emit_output(&data);
                 }
             }
+            if done {
+                // Output that ends without a newline still gets matched
+                run_triggers(&reader_app, session_id, &reader_terminal, || {
+                    lines.finish(stream_offset).into_iter().collect()
+                });
+                break;
+            }
         }
+        reader_log.stop();
+        // Dropping the sender lets the forwarder flush and end the session
     });
 
     let child_pid = child.process_id().unwrap_or(0);
+    let killer = child.clone_killer();
+    app.state::<Notifier>().add_session(session_id);
+
+    // Wait for the child so its exit can be reported with the end of the output
+    let wait_output = output.clone();
+    let wait_app = app.clone();
+    let exits = state.exits.clone();
+    let started_at = options.started_at;
+    let wait_thread = thread::spawn(move || {
+        let status = child.wait().unwrap_or_else(|_| ExitStatus::with_exit_code(1));
+        let exit = PtyExit::new(&status, started_at.elapsed().unwrap_or_default());
+
+        let mut exits = exits.lock().unwrap();
+        exits.retain(|_, (_, at)| at.elapsed() < EXIT_RECORD_TTL);
+        exits.insert(session_id, (exit.clone(), Instant::now()));
+        drop(exits);
+
+        let body = match &exit.signal {
+            Some(signal) => format!("Ended by signal: {}", signal),
+            None => format!("Exited with code {}", exit.code),
+        };
+        wait_output.set_exit(exit);
+        wait_app
+            .state::<Notifier>()
+            .notify(&wait_app, session_id, NotificationKind::SessionExited, &body);
+    });
+
     let session = PtySession {
-        master: pair.master,
-        writer,
-        child,
+        master: std::sync::Mutex::new(master),
+        #[cfg(unix)]
+        host: state.started_host().map(|host| (host.clone(), session_id)),
+        input,
+        killer,
         child_pid,
-        _reader_thread: reader_thread,
+        cwd: options.cwd,
+        created_at: started_at
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        output,
+        terminal,
+        screen,
+        clipboard,
+        recording,
+        log,
+        threads: std::sync::Mutex::new(Some(SessionThreads {
+            reader: reader_thread,
+            forward: forward_thread,
+            wait: wait_thread,
+        })),
     };
 
     let mut sessions = state.sessions.lock().await;
-    sessions.insert(session_id, session);
+    sessions.insert(session_id, Arc::new(session));
 
-    Ok(session_id)
+    Ok(())
+}
+
+/// Run scripted steps against a session: wait for the prompt or a pattern, send text
+/// or keys, capture output. Stops at the first step that fails. Output is read from
+/// `from_offset`, by default from where input was last sent.
+#[tauri::command]
+async fn run_script_in_pty(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    steps: Vec<ScriptStep>,
+    from_offset: Option<u64>,
+) -> Result<ScriptResult, String> {
+    let script = pty_script::compile(steps)?;
+    let session = state.session(session_id).await?;
+    tauri::async_runtime::spawn_blocking(move || pty_script::run(&*session, &script, from_offset))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))
+}
+
+/// Queue input for a session and note when the user last typed. Fails rather than
+/// waits when the child has stopped reading and the queue is full.
+fn write_input(session: &PtySession, data: &[u8]) -> Result<(), String> {
+    session.input.try_send(data.to_vec()).map_err(|e| match e {
+        TrySendError::Full(_) => "Session is not reading input".to_string(),
+        TrySendError::Disconnected(_) => "Session input is closed".to_string(),
+    })?;
+    session.recording.input(data);
+    let offset = session.output.lock().scrollback.end_offset();
+    session.terminal.lock().unwrap().record_input(offset);
+    Ok(())
 }
 
 #[tauri::command]
@@ -248,17 +756,70 @@ async fn write_to_pty(
     session_id: u32,
     data: String,
 ) -> Result<(), String> {
-    let mut sessions = state.sessions.lock().await;
-    if let Some(session) = sessions.get_mut(&session_id) {
-        session
-            .writer
-            .write_all(data.as_bytes())
-            .map_err(|e| format!("Write error: {}", e))?;
-        session.writer.flush().map_err(|e| format!("Flush error: {}", e))?;
-        Ok(())
+    let session = state.session(session_id).await?;
+    write_input(&session, data.as_bytes())
+}
+
+#[derive(serde::Serialize)]
+struct PtyWriteResult {
+    session_id: u32,
+    /// Why the input didn't reach the session, e.g. "Session not found"
+    error: Option<String>,
+}
+
+/// Send the same input to several sessions: those listed plus the members of `group`.
+/// Sessions that are gone or fail don't stop the rest; each gets its own result.
+#[tauri::command]
+async fn write_to_pty_group(
+    state: State<'_, PtyState>,
+    session_ids: Vec<u32>,
+    group: Option<String>,
+    data: String,
+) -> Result<Vec<PtyWriteResult>, String> {
+    let mut targets = session_ids;
+    if let Some(name) = group {
+        let groups = state.groups.lock().unwrap();
+        let members = groups.get(&name).ok_or_else(|| format!("Group not found: {}", name))?;
+        targets.extend(members);
+    }
+    let mut seen = HashSet::new();
+    targets.retain(|id| seen.insert(*id));
+
+    let sessions = state.sessions.lock().await;
+    let results = targets
+        .into_iter()
+        .map(|session_id| {
+            let result = sessions
+                .get(&session_id)
+                .ok_or_else(|| "Session not found".to_string())
+                .and_then(|session| write_input(session, data.as_bytes()));
+            PtyWriteResult {
+                session_id,
+                error: result.err(),
+            }
+        })
+        .collect();
+    Ok(results)
+}
+
+/// Name a group of sessions, replacing any group of that name. An empty list removes it.
+#[tauri::command]
+fn set_pty_group(state: State<'_, PtyState>, name: String, session_ids: Vec<u32>) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err("Group name is required".to_string());
+    }
+    let mut groups = state.groups.lock().unwrap();
+    if session_ids.is_empty() {
+        groups.remove(&name);
     } else {
-        Err("Session not found".to_string())
+        groups.insert(name, session_ids);
     }
+    Ok(())
+}
+
+#[tauri::command]
+fn list_pty_groups(state: State<'_, PtyState>) -> HashMap<String, Vec<u32>> {
+    state.groups.lock().unwrap().clone()
 }
 
 
@@ -269,117 +830,826 @@ async fn resize_pty(
     rows: u16,
     cols: u16,
 ) -> Result<(), String> {
-    let sessions = state.sessions.lock().await;
-    if let Some(session) = sessions.get(&session_id) {
-        session.master.resize(PtySize {
+    let session = state.session(session_id).await?;
+    // A hosted session's master resizes through a blocking request to the PTY host
+    tauri::async_runtime::spawn_blocking(move || {
+        session.master.lock().unwrap().resize(PtySize {
             rows,
             cols,
             pixel_width: 0,
             pixel_height: 0,
         }).map_err(|e| format!("Resize error: {}", e))?;
+        session.screen.lock().unwrap().resize(rows, cols);
+        session.recording.resize(cols, rows);
         Ok(())
-    } else {
-        Err("Session not found".to_string())
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Shutdown reports kept for `get_pty_shutdown_reports`.
+const MAX_SHUTDOWN_REPORTS: usize = 50;
+/// How long a shutdown waits for a session's threads once its processes are gone.
+const THREAD_JOIN_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Join a thread if it finishes by `deadline`. Returns whether it did.
+fn join_before(handle: thread::JoinHandle<()>, deadline: Instant) -> bool {
+    while !handle.is_finished() {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    handle.join().is_ok()
+}
+
+/// End a session for good: hang up its process tree, kill whatever outlives `grace`,
+/// then wait for the shell to be reaped and the session's threads to finish. Blocks.
+fn shutdown_session(app: &AppHandle, session_id: u32, session: Arc<PtySession>, grace: Duration) -> ShutdownReport {
+    let started = Instant::now();
+    let mut report = end_session_processes(app, session_id, &session, grace);
+
+    let threads = session.threads.lock().unwrap().take();
+    // The reader ends once nothing holds the terminal open, the forwarder after it,
+    // which mustn't be left waiting on a frontend that stopped acking
+    session.output.release();
+    drop(session);
+    let deadline = Instant::now() + THREAD_JOIN_TIMEOUT;
+    report.reaped = threads.is_some_and(|threads| {
+        [threads.wait, threads.reader, threads.forward]
+            .into_iter()
+            .all(|handle| join_before(handle, deadline))
+    });
+    report.elapsed_ms = started.elapsed().as_millis() as u64;
+    report
+}
+
+/// Sessions in the PTY host are ended by the host, which owns their processes.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn end_session_processes(app: &AppHandle, session_id: u32, session: &PtySession, grace: Duration) -> ShutdownReport {
+    #[cfg(unix)]
+    if let Some(host) = app.state::<PtyState>().started_host() {
+        return host.shutdown_session(session_id, grace).unwrap_or_else(|_| {
+            // A host from an older version only knows how to hang up the shell
+            let _ = session.killer.clone_killer().kill();
+            ShutdownReport {
+                session_id,
+                ..Default::default()
+            }
+        });
     }
+    shutdown::end_process_tree(session_id, session.child_pid, grace)
 }
 
+/// Keep a shutdown report and emit it as `pty-session-shutdown`.
+fn record_shutdown(app: &AppHandle, report: ShutdownReport) {
+    let state = app.state::<PtyState>();
+    let mut shutdowns = state.shutdowns.lock().unwrap();
+    if shutdowns.len() == MAX_SHUTDOWN_REPORTS {
+        shutdowns.pop_front();
+    }
+    shutdowns.push_back(report.clone());
+    drop(shutdowns);
+    let _ = app.emit("pty-session-shutdown", report);
+}
+
+/// Close a session and end everything running in it. Processes get `grace_ms` to exit
+/// after SIGHUP before they are killed. Returns right away; the report follows as
+/// `pty-session-shutdown`.
 #[tauri::command]
-async fn close_pty_session(state: State<'_, PtyState>, session_id: u32) -> Result<(), String> {
+async fn close_pty_session(
+    app: AppHandle,
+    state: State<'_, PtyState>,
+    notifier: State<'_, Notifier>,
+    session_id: u32,
+    grace_ms: Option<u64>,
+) -> Result<(), String> {
+    // Closing a session on purpose isn't worth a notification
+    notifier.remove_session(session_id);
+    state.groups.lock().unwrap().retain(|_, members| {
+        members.retain(|&id| id != session_id);
+        !members.is_empty()
+    });
     let session = {
         let mut sessions = state.sessions.lock().await;
         sessions.remove(&session_id)
     };
 
-    if let Some(mut session) = session {
-        // Kill and drop everything - don't wait
-        let _ = session.child.kill();
-        drop(session.master);
-        drop(session.writer);
-        // Reader thread and child process will clean up on their own
-        Ok(())
-    } else {
-        Ok(())
+    if let Some(session) = session {
+        let grace = grace_ms.map(Duration::from_millis).unwrap_or(SHUTDOWN_GRACE);
+        thread::spawn(move || {
+            let report = shutdown_session(&app, session_id, session, grace);
+            record_shutdown(&app, report);
+        });
     }
+    Ok(())
 }
 
+/// What recent shutdowns found and killed, oldest first. Useful when a port stays taken.
 #[tauri::command]
-async fn get_pty_foreground_process(
+fn get_pty_shutdown_reports(state: State<'_, PtyState>) -> Vec<ShutdownReport> {
+    state.shutdowns.lock().unwrap().iter().cloned().collect()
+}
+
+/// On quit, end the sessions this process owns and wait for them. Sessions in the
+/// PTY host keep running so the next launch can resume them.
+fn shutdown_all_sessions(app: &AppHandle) {
+    let state = app.state::<PtyState>();
+    if state.hosted() {
+        return;
+    }
+    let sessions: Vec<(u32, Arc<PtySession>)> =
+        tauri::async_runtime::block_on(async { state.sessions.lock().await.drain().collect() });
+
+    let notifier = app.state::<Notifier>();
+    let shutdowns: Vec<_> = sessions
+        .into_iter()
+        .map(|(session_id, session)| {
+            notifier.remove_session(session_id);
+            let app = app.clone();
+            thread::spawn(move || shutdown_session(&app, session_id, session, SHUTDOWN_GRACE))
+        })
+        .collect();
+    for shutdown in shutdowns {
+        let _ = shutdown.join();
+    }
+}
+
+#[tauri::command]
+async fn get_pty_scrollback(
     state: State<'_, PtyState>,
     session_id: u32,
-) -> Result<String, String> {
-    let child_pid = {
+) -> Result<PtyScrollback, String> {
+    let session = state.session(session_id).await?;
+    let output = session.output.lock();
+    let (data, start_offset) = output.scrollback.snapshot();
+    Ok(PtyScrollback {
+        data,
+        start_offset,
+        end_offset: output.scrollback.end_offset(),
+    })
+}
+
+/// Search the scrollback of the given sessions, or of every session, as plain text.
+/// `query` is a literal string unless `regex` is set; case is ignored unless `case_sensitive` is set.
+/// Sessions that no longer exist are skipped.
+#[tauri::command]
+async fn search_pty_output(
+    state: State<'_, PtyState>,
+    query: String,
+    regex: Option<bool>,
+    case_sensitive: Option<bool>,
+    session_ids: Option<Vec<u32>>,
+) -> Result<SearchResults, String> {
+    let pattern = search::build_query(&query, regex.unwrap_or(false), case_sensitive.unwrap_or(false))?;
+
+    let mut sessions: Vec<(u32, Arc<PtySession>)> = {
         let sessions = state.sessions.lock().await;
-        sessions.get(&session_id).map(|s| s.child_pid)
+        sessions
+            .iter()
+            .filter(|(id, _)| session_ids.as_ref().is_none_or(|ids| ids.contains(id)))
+            .map(|(&session_id, session)| (session_id, session.clone()))
+            .collect()
     };
+    sessions.sort_by_key(|(session_id, _)| *session_id);
+
+    tauri::async_runtime::spawn_blocking(move || {
+        // Copied after the session map is released, holding one session's output at a time
+        let targets: Vec<SearchTarget> = sessions
+            .into_iter()
+            .map(|(session_id, session)| {
+                let (bytes, start_offset) = session.output.lock().scrollback.snapshot_bytes();
+                SearchTarget { session_id, bytes, start_offset }
+            })
+            .collect();
+        search::search(&targets, &pattern)
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))
+}
+
+/// Connect a terminal view to a session's output, e.g. on mount or after a webview reload.
+/// The channel first receives the scrollback, then live output in order,
+/// and an end message carrying the exit status once the session is over. It replaces any previous channel.
+#[tauri::command]
+#[cfg_attr(not(unix), allow(unused_variables))]
+async fn attach_pty_session(
+    app: AppHandle,
+    state: State<'_, PtyState>,
+    session_id: u32,
+    on_event: Channel<InvokeResponseBody>,
+) -> Result<(), String> {
+    #[cfg(unix)]
+    adopt_host_session(&app, &state, session_id).await?;
+
+    let session = state.session(session_id).await?;
+
+    session.output.attach(on_event);
+    Ok(())
+}
+
+/// Acknowledge that the frontend has rendered `bytes` of a session's output.
+/// Output is held back while too much of it is unacknowledged.
+#[tauri::command]
+async fn ack_pty_output(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    bytes: usize,
+) -> Result<(), String> {
+    let session = state.session(session_id).await?;
+    session.output.ack(bytes);
+    Ok(())
+}
+
+#[tauri::command]
+async fn set_pty_flow_control(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    config: FlowConfig,
+) -> Result<(), String> {
+    let session = state.session(session_id).await?;
+    session.output.set_flow(config);
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_pty_output_stats(
+    state: State<'_, PtyState>,
+    session_id: u32,
+) -> Result<OutputStats, String> {
+    let session = state.session(session_id).await?;
+    Ok(session.output.stats())
+}
+
+/// How a session's child ended: `None` while it's still running. Records are kept
+/// for a while after the session is closed.
+#[tauri::command]
+async fn get_pty_exit_status(
+    state: State<'_, PtyState>,
+    session_id: u32,
+) -> Result<Option<PtyExit>, String> {
+    let running = state.sessions.lock().await.contains_key(&session_id);
+    let mut exits = state.exits.lock().unwrap();
+    exits.retain(|_, (_, at)| at.elapsed() < EXIT_RECORD_TTL);
+    match exits.get(&session_id) {
+        Some((exit, _)) => Ok(Some(exit.clone())),
+        None if running => Ok(None),
+        None => Err("Session not found".to_string()),
+    }
+}
 
-    let Some(shell_pid) = child_pid else {
-        return Err("Session not found".to_string());
+/// Take over a session the PTY host kept running from a previous app run.
+/// The host replays its buffered output, which lands in the new scrollback.
+#[cfg(unix)]
+async fn adopt_host_session(app: &AppHandle, state: &PtyState, session_id: u32) -> Result<(), String> {
+    let _adopting = state.adopting.lock().await;
+    if state.sessions.lock().await.contains_key(&session_id) {
+        return Ok(());
+    }
+    let Some(host) = state.host().await else {
+        return Ok(());
+    };
+    let (info, (master, child)) = tauri::async_runtime::spawn_blocking({
+        let host = host.clone();
+        move || host.open_session(session_id)
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))??;
+    let options = SessionOptions {
+        cwd: info.cwd,
+        scrollback_limit: info.scrollback_limit,
+        flow: FlowConfig::default(),
+        started_at: UNIX_EPOCH + Duration::from_secs(info.created_at),
+        replay_bytes: info.replay_bytes,
     };
+    start_session(app, state, session_id, master, child, options).await
+}
 
-    tauri::async_runtime::spawn_blocking(move || {
-        // Get shell name first
-        let shell_name = Command::new("ps")
-            .args(["-o", "comm=", "-p", &shell_pid.to_string()])
-            .output()
-            .ok()
-            .filter(|o| o.status.success())
-            .map(|o| {
-                let name = String::from_utf8_lossy(&o.stdout).trim().to_string();
-                let name = name.split('/').last().unwrap_or(&name);
-                // Remove leading '-' from login shells (e.g., "-zsh" -> "zsh")
-                name.strip_prefix('-').unwrap_or(name).to_string()
+/// Sessions of this app instance plus, on Unix, those the PTY host kept
+/// from earlier runs. Pass an id to `attach_pty_session` to resume it.
+#[tauri::command]
+async fn list_pty_sessions(
+    state: State<'_, PtyState>,
+    notifier: State<'_, Notifier>,
+) -> Result<Vec<PtySessionSummary>, String> {
+    let mut summaries: Vec<PtySessionSummary> = {
+        let sessions = state.sessions.lock().await;
+        sessions
+            .iter()
+            .map(|(id, s)| PtySessionSummary {
+                session_id: *id,
+                pid: s.child_pid,
+                cwd: s.cwd.clone(),
+                created_at: s.created_at,
+                attached: s.output.has_channel(),
+                exited: s.output.lock().ended,
+                issue_key: notifier.issue_key(*id),
             })
-            .unwrap_or_else(|| "shell".to_string());
+            .collect()
+    };
 
-        if shell_pid == 0 {
-            return shell_name;
+    #[cfg(unix)]
+    if let Some(host) = state.host().await {
+        let host_sessions = tauri::async_runtime::spawn_blocking({
+            let host = host.clone();
+            move || host.list_sessions()
+        })
+        .await
+        .map_err(|e| format!("Task error: {}", e))??;
+
+        for info in host_sessions {
+            let exited = info.exit_status.is_some();
+            if !summaries.iter().any(|s| s.session_id == info.id) {
+                summaries.push(PtySessionSummary {
+                    session_id: info.id,
+                    pid: info.pid,
+                    cwd: info.cwd,
+                    created_at: info.created_at,
+                    attached: false,
+                    exited,
+                    issue_key: notifier.issue_key(info.id),
+                });
+            }
         }
+    }
 
-        // Get shell's tty
-        let tty = Command::new("ps")
-            .args(["-o", "tty=", "-p", &shell_pid.to_string()])
-            .output()
-            .ok()
-            .filter(|o| o.status.success())
-            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
-            .unwrap_or_default();
+    summaries.sort_by_key(|s| s.session_id);
+    Ok(summaries)
+}
+
+/// Foreground job of a session, e.g. `claude` or `vim`, or the shell itself when idle.
+#[derive(serde::Serialize)]
+struct PtyForegroundProcess {
+    #[serde(flatten)]
+    process: ProcessInfo,
+    /// No job is running; the session's own shell is in the foreground
+    is_shell: bool,
+}
 
-        if tty.is_empty() || tty == "??" {
-            return shell_name;
+/// Look up the foreground job of a session. For shells that
+/// don't report their directory with OSC 7, the job's cwd becomes the session's cwd.
+async fn foreground_process(
+    app: &AppHandle,
+    state: &PtyState,
+    session_id: u32,
+) -> Result<PtyForegroundProcess, String> {
+    let session = state.session(session_id).await?;
+    let terminal = session.terminal.clone();
+
+    let foreground = tauri::async_runtime::spawn_blocking(move || {
+        let shell_pid = session.child_pid;
+        let leader = session
+            .foreground_pgid()
+            .filter(|&pgid| pgid != shell_pid)
+            .and_then(process_info::process_info);
+        match leader {
+            Some(process) => Ok(PtyForegroundProcess { process, is_shell: false }),
+            None => process_info::process_info(shell_pid)
+                .map(|process| PtyForegroundProcess { process, is_shell: true })
+                .ok_or_else(|| "Process not found".to_string()),
         }
+    })
+    .await
+    .map_err(|e| format!("Task error: {}", e))??;
 
-        // Find foreground process on this tty (stat contains '+')
-        let ps_output = Command::new("ps")
-            .args(["-t", &tty, "-o", "pid=,stat=,comm="])
-            .output();
-
-        if let Ok(output) = ps_output {
-            if output.status.success() {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                for line in stdout.lines() {
-                    let parts: Vec<&str> = line.split_whitespace().collect();
-                    if parts.len() >= 3 {
-                        let pid = parts[0];
-                        let stat = parts[1];
-                        let comm = parts[2];
-
-                        // Foreground process has '+' in stat and is not the shell
-                        if stat.contains('+') && pid != shell_pid.to_string() {
-                            let name = comm.split('/').last().unwrap_or(comm);
-                            let name = name.strip_prefix('-').unwrap_or(name);
-                            if name != shell_name {
-                                return name.to_string();
-                            }
-                        }
-                    }
-                }
-            }
+    if let Some(cwd) = &foreground.process.cwd {
+        let event = terminal.lock().unwrap().set_cwd(cwd.clone(), CwdSource::Process);
+        if let Some(event) = event {
+            emit_terminal_event(app, session_id, event);
         }
+    }
+    Ok(foreground)
+}
 
-        shell_name
+#[tauri::command]
+async fn get_pty_foreground_process(
+    app: AppHandle,
+    state: State<'_, PtyState>,
+    session_id: u32,
+) -> Result<PtyForegroundProcess, String> {
+    foreground_process(&app, &state, session_id).await
+}
+
+/// Process group of the job in the foreground of a session, if it isn't just the shell.
+async fn foreground_job(state: &PtyState, session_id: u32) -> Result<u32, String> {
+    let session = state.session(session_id).await?;
+    tauri::async_runtime::spawn_blocking(move || {
+        session
+            .foreground_pgid()
+            .filter(|&pgid| pgid != session.child_pid)
+            .ok_or_else(|| "No foreground job".to_string())
     })
     .await
-    .map_err(|e| format!("Task error: {}", e))
+    .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Send a signal to the foreground job of a session, e.g. SIGINT as Ctrl-C would,
+/// or SIGSTOP and SIGCONT to pause it. The shell itself is never signalled.
+#[tauri::command]
+async fn signal_pty_foreground(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    signal: PtySignal,
+) -> Result<(), String> {
+    let pgid = foreground_job(&state, session_id).await?;
+    signals::send(pgid, signal)
+}
+
+/// Stop the foreground job of a session, such as a runaway agent, and keep the shell.
+/// Sends SIGINT, then SIGTERM, then SIGKILL, waiting `grace_ms` after each.
+/// Returns the signal that ended it.
+#[tauri::command]
+async fn stop_pty_foreground(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    grace_ms: Option<u64>,
+) -> Result<PtySignal, String> {
+    let pgid = foreground_job(&state, session_id).await?;
+    let grace = grace_ms.map(Duration::from_millis).unwrap_or(signals::STOP_GRACE);
+    tauri::async_runtime::spawn_blocking(move || signals::stop(pgid, grace))
+        .await
+        .map_err(|e| format!("Task error: {}", e))?
+}
+
+/// Commands run in a session, oldest first, as marked by shell integration.
+/// The last one has no exit code yet if it's still running.
+#[tauri::command]
+async fn get_pty_command_history(
+    state: State<'_, PtyState>,
+    session_id: u32,
+) -> Result<Vec<CommandRecord>, String> {
+    let session = state.session(session_id).await?;
+    let history = session.terminal.lock().unwrap().command_history();
+    Ok(history)
+}
+
+/// What a session's screen shows right now, as plain text and styled runs of cells.
+#[tauri::command]
+async fn get_pty_screen(
+    state: State<'_, PtyState>,
+    session_id: u32,
+) -> Result<ScreenSnapshot, String> {
+    let session = state.session(session_id).await?;
+    let snapshot = session.screen.lock().unwrap().snapshot();
+    Ok(snapshot)
+}
+
+/// Let programs in a session set the clipboard, and read it, through OSC 52.
+#[tauri::command]
+async fn set_pty_clipboard_access(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    access: ClipboardAccess,
+) -> Result<(), String> {
+    let session = state.session(session_id).await?;
+    *session.clipboard.lock().unwrap() = access;
+    Ok(())
+}
+
+/// Lines that output triggers marked in a session, oldest first.
+#[tauri::command]
+async fn get_pty_markers(
+    state: State<'_, PtyState>,
+    session_id: u32,
+) -> Result<Vec<TriggerMatch>, String> {
+    let session = state.session(session_id).await?;
+    let markers = session.terminal.lock().unwrap().markers();
+    Ok(markers)
+}
+
+/// File references in a line of a session's output, resolved against the session's
+/// directories. The view asks for the lines it shows as the pointer reaches them,
+/// so output isn't scanned and checked against the file system as it streams.
+#[tauri::command]
+async fn find_pty_file_links(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    text: String,
+) -> Result<Vec<FileLink>, String> {
+    let session = state.session(session_id).await?;
+    let dirs = link_dirs(&session.terminal, session.cwd.as_deref());
+    tauri::async_runtime::spawn_blocking(move || file_links::find_links(&text, &dirs))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))
+}
+
+/// Record a session to an asciinema v2 `.cast` file, filed under its issue in the
+/// app data dir. Input is only recorded when asked for, as it may hold passwords.
+#[tauri::command]
+async fn start_pty_recording(
+    state: State<'_, PtyState>,
+    notifier: State<'_, Notifier>,
+    session_id: u32,
+    record_input: Option<bool>,
+) -> Result<RecordingInfo, String> {
+    let session = state.session(session_id).await?;
+    if session.recording.path().is_some() {
+        return Err("Session is already being recorded".to_string());
+    }
+    let size = session.master.lock().unwrap().get_size().map_err(|e| format!("Size error: {}", e))?;
+    let issue_key = notifier.issue_key(session_id);
+    let recorder = Recorder::create(
+        session_id,
+        issue_key.as_deref(),
+        size.cols,
+        size.rows,
+        record_input.unwrap_or(false),
+    )?;
+    let path = recorder.path().to_path_buf();
+    session.recording.start(recorder)?;
+    recording::recording_info(&path)
+}
+
+#[tauri::command]
+async fn stop_pty_recording(state: State<'_, PtyState>, session_id: u32) -> Result<RecordingInfo, String> {
+    let session = state.session(session_id).await?;
+    let path = session.recording.stop()?;
+    recording::recording_info(&path)
+}
+
+/// Recordings filed under an issue, or all of them, newest first.
+#[tauri::command]
+async fn list_pty_recordings(issue_key: Option<String>) -> Result<Vec<RecordingInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || recording::list_recordings(issue_key.as_deref()))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
+#[derive(serde::Serialize)]
+struct PtyPlaybackInfo {
+    playback_id: u32,
+    /// Terminal size the recording starts at
+    cols: u16,
+    rows: u16,
+    duration_ms: u64,
+}
+
+/// Play a recording into a read-only terminal view. The channel gets output and
+/// resize messages at `speed` times the recorded pace, then an end message.
+/// Pauses are shortened to `max_idle_ms`, or to the limit the recording sets.
+/// Only recordings this app made, as listed by `list_pty_recordings`, can be played.
+#[tauri::command]
+async fn play_pty_recording(
+    app: AppHandle,
+    playbacks: State<'_, Playbacks>,
+    path: String,
+    speed: Option<f64>,
+    max_idle_ms: Option<u64>,
+    on_event: Channel<InvokeResponseBody>,
+) -> Result<PtyPlaybackInfo, String> {
+    let cast = tauri::async_runtime::spawn_blocking(move || {
+        let path = Path::new(&path);
+        if !recording::is_known_recording(path) {
+            return Err("Not a session recording".to_string());
+        }
+        recording::load(path)
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))??;
+    let (playback_id, control) = playbacks.start(speed.unwrap_or(1.0))?;
+    let info = PtyPlaybackInfo {
+        playback_id,
+        cols: cast.header.width,
+        rows: cast.header.height,
+        duration_ms: cast.duration().as_millis() as u64,
+    };
+
+    let idle_limit = max_idle_ms
+        .map(|ms| ms as f64 / 1000.0)
+        .or(cast.header.idle_time_limit);
+    thread::spawn(move || {
+        recording::play(&cast, idle_limit, &control, &on_event);
+        app.state::<Playbacks>().finish(playback_id);
+    });
+    Ok(info)
+}
+
+#[tauri::command]
+fn set_pty_playback_speed(playbacks: State<'_, Playbacks>, playback_id: u32, speed: f64) -> Result<(), String> {
+    playbacks.get(playback_id)?.set_speed(speed)
+}
+
+#[tauri::command]
+fn stop_pty_playback(playbacks: State<'_, Playbacks>, playback_id: u32) -> Result<(), String> {
+    playbacks.get(playback_id)?.stop();
+    Ok(())
+}
+
+/// Log a session's output as plain text, rotating and expiring logs as `config` says,
+/// or stop logging it with `None`. Returns the file being written to.
+#[tauri::command]
+async fn set_pty_logging(
+    state: State<'_, PtyState>,
+    notifier: State<'_, Notifier>,
+    session_id: u32,
+    config: Option<LogConfig>,
+) -> Result<Option<String>, String> {
+    let session = state.session(session_id).await?;
+    let Some(config) = config else {
+        session.log.stop();
+        return Ok(None);
+    };
+
+    let dir = output_log::log_dir(session.cwd.as_deref())
+        .ok_or_else(|| "Could not determine log directory".to_string())?;
+    let file_name = output_log::log_file_name(session_id, notifier.issue_key(session_id).as_deref());
+    let writer = tauri::async_runtime::spawn_blocking(move || LogWriter::create(&dir, &file_name, config))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))??;
+    session.log.start(writer);
+    Ok(session.log.path().map(|path| path.to_string_lossy().into_owned()))
+}
+
+/// Output logs of an issue's sessions, or of all sessions, newest first.
+#[tauri::command]
+async fn list_pty_logs(issue_key: Option<String>) -> Result<Vec<LogFileInfo>, String> {
+    tauri::async_runtime::spawn_blocking(move || output_log::list_logs(issue_key.as_deref()))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))
+}
+
+/// Open a file in the app the system associates with it.
+fn open_with_default_app(path: &Path) -> std::io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let mut command = Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        // The empty argument is the window title `start` would otherwise take the path for
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = Command::new("xdg-open");
+    command.arg(path).output()?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn open_pty_log(path: String) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        if !output_log::is_known_log(Path::new(&path)) {
+            return Err("Not a session log".to_string());
+        }
+        open_with_default_app(Path::new(&path)).map_err(|e| format!("Failed to open log: {}", e))
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
+#[derive(serde::Serialize)]
+struct PtySessionInfo {
+    session_id: u32,
+    pid: u32,
+    /// Directory the session was started in
+    initial_cwd: Option<String>,
+    /// Current directory, as last reported by the shell or read from the foreground process
+    cwd: Option<String>,
+    cwd_source: CwdSource,
+    /// Seconds since the Unix epoch
+    created_at: u64,
+    rows: u16,
+    cols: u16,
+    exited: bool,
+    foreground: Option<PtyForegroundProcess>,
+    activity: ActivityState,
+    agent: Option<&'static str>,
+    clipboard: ClipboardAccess,
+    /// Where the session is being recorded to, if it is
+    recording: Option<String>,
+    /// Where the session's output is being logged to, if it is
+    log: Option<String>,
+    /// Why logging stopped writing, if it did
+    log_error: Option<String>,
+}
+
+#[tauri::command]
+async fn get_pty_session_info(
+    app: AppHandle,
+    state: State<'_, PtyState>,
+    session_id: u32,
+) -> Result<PtySessionInfo, String> {
+    // Refreshes the cwd for shells without OSC 7 support
+    let foreground = foreground_process(&app, &state, session_id).await.ok();
+
+    let session = state.session(session_id).await?;
+    let size = session.master.lock().unwrap().get_size().map_err(|e| format!("Size error: {}", e))?;
+    let terminal = session.terminal.lock().unwrap();
+    Ok(PtySessionInfo {
+        session_id,
+        pid: session.child_pid,
+        initial_cwd: session.cwd.clone(),
+        cwd: terminal.cwd.clone(),
+        cwd_source: terminal.cwd_source,
+        created_at: session.created_at,
+        rows: size.rows,
+        cols: size.cols,
+        exited: session.output.lock().ended,
+        foreground,
+        activity: terminal.activity,
+        agent: terminal.agent,
+        clipboard: *session.clipboard.lock().unwrap(),
+        recording: session
+            .recording
+            .path()
+            .map(|path| path.to_string_lossy().into_owned()),
+        log: session.log.path().map(|path| path.to_string_lossy().into_owned()),
+        log_error: session.log.error(),
+    })
+}
+
+/// Classify every session's activity on a timer, emitting `pty-state-changed` when it changes.
+fn watch_activity(app: AppHandle) {
+    thread::spawn(move || loop {
+        thread::sleep(activity::POLL_INTERVAL);
+        tauri::async_runtime::block_on(update_activity(&app));
+    });
+}
+
+async fn update_activity(app: &AppHandle) {
+    let state = app.state::<PtyState>();
+    let session_ids: Vec<u32> = state.sessions.lock().await.keys().copied().collect();
+
+    for session_id in session_ids {
+        let Some((output, terminal)) = state
+            .sessions
+            .lock()
+            .await
+            .get(&session_id)
+            .map(|s| (s.output.clone(), s.terminal.clone()))
+        else {
+            continue;
+        };
+        let ended = output.lock().ended;
+        let foreground = if ended {
+            None
+        } else {
+            foreground_process(app, &state, session_id).await.ok()
+        };
+        let job = foreground.as_ref().filter(|f| !f.is_shell).map(|f| &f.process);
+
+        let (input_offset, quiet_for) = {
+            let terminal = terminal.lock().unwrap();
+            (terminal.input_offset(), terminal.quiet_for())
+        };
+        let recent_output = output
+            .lock()
+            .scrollback
+            .tail(input_offset, activity::PROMPT_SCAN_BYTES);
+        let activity = activity::classify(&ActivitySample {
+            ended,
+            quiet_for,
+            recent_output: &recent_output,
+            job,
+        });
+
+        let event = terminal
+            .lock()
+            .unwrap()
+            .set_activity(activity, job.and_then(activity::agent_name));
+        if let Some(event) = event {
+            emit_terminal_event(app, session_id, event);
+        }
+    }
+}
+
+#[tauri::command]
+fn get_notification_settings(notifier: State<'_, Notifier>) -> NotificationSettings {
+    notifier.settings()
+}
+
+#[tauri::command]
+fn save_notification_settings(
+    notifier: State<'_, Notifier>,
+    settings: NotificationSettings,
+) -> Result<(), String> {
+    notifier.set_settings(settings)
+}
+
+/// Tie a session to the issue it was opened for, for per-issue muting and
+/// so clicking its notifications opens that issue.
+#[tauri::command]
+fn set_pty_session_issue(
+    notifier: State<'_, Notifier>,
+    session_id: u32,
+    issue_key: Option<String>,
+) {
+    notifier.set_issue(session_id, issue_key);
+}
+
+#[tauri::command]
+fn set_pty_notifications_muted(
+    notifier: State<'_, Notifier>,
+    session_id: u32,
+    muted: bool,
+) {
+    notifier.set_muted(session_id, muted);
+}
+
+/// The terminal the user is looking at. It doesn't notify while the window has focus.
+#[tauri::command]
+fn set_focused_pty_session(notifier: State<'_, Notifier>, session_id: Option<u32>) {
+    notifier.set_focused_session(session_id);
 }
 
 #[tauri::command]
@@ -397,14 +1667,7 @@ async fn filter_real_files(base_path: String, paths: Vec<String>) -> Vec<String>
         let base = Path::new(&base_path);
         paths
             .into_iter()
-            .filter(|p| {
-                let full_path = base.join(p);
-                // Check if it's a regular file (not symlink, not directory)
-                match std::fs::symlink_metadata(&full_path) {
-                    Ok(meta) => meta.is_file() && !meta.file_type().is_symlink(),
-                    Err(_) => false,
-                }
-            })
+            .filter(|p| file_links::is_real_file(&base.join(p)))
             .collect()
     })
     .await
@@ -414,16 +1677,9 @@ async fn filter_real_files(base_path: String, paths: Vec<String>) -> Vec<String>
 #[tauri::command]
 async fn run_git_command(cwd: String, args: Vec<String>) -> Result<String, String> {
     tauri::async_runtime::spawn_blocking(move || {
-        let path_env = std::env::var("PATH").unwrap_or_default();
-        let extended_path = format!(
-            "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin:{}",
-            path_env
-        );
-
-        let output = Command::new("git")
+        let output = shell_env::command("git")
             .args(&args)
             .current_dir(&cwd)
-            .env("PATH", &extended_path)
             .output()
             .map_err(|e| format!("Failed to execute git: {}", e))?;
 
@@ -473,13 +1729,95 @@ async fn write_file(path: String, content: String) -> Result<(), String> {
     .map_err(|e| format!("Task join error: {}", e))?
 }
 
+fn app_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|p| p.join("com.jeonghyeon.net"))
+}
+
 #[tauri::command]
 fn get_app_data_dir() -> Result<String, String> {
-    dirs::data_dir()
-        .map(|p| p.join("com.jeonghyeon.net").to_string_lossy().to_string())
+    app_data_dir()
+        .map(|p| p.to_string_lossy().to_string())
         .ok_or_else(|| "Could not determine app data directory".to_string())
 }
 
+/// The login-shell environment used for PTYs, git and gh, and where it came from.
+#[tauri::command]
+async fn get_resolved_environment() -> Result<shell_env::ResolvedEnv, String> {
+    tauri::async_runtime::spawn_blocking(|| shell_env::resolved().clone())
+        .await
+        .map_err(|e| format!("Task join error: {}", e))
+}
+
+#[tauri::command]
+async fn list_shell_profiles() -> Result<Vec<ShellProfile>, String> {
+    shell_profiles::load_profiles()
+}
+
+/// Add a profile, or replace the one with the same name.
+#[tauri::command]
+async fn save_shell_profile(profile: ShellProfile) -> Result<(), String> {
+    if profile.name.trim().is_empty() {
+        return Err("Profile name is required".to_string());
+    }
+    let mut profiles = shell_profiles::load_profiles()?;
+    match profiles.iter_mut().find(|p| p.name == profile.name) {
+        Some(existing) => *existing = profile,
+        None => profiles.push(profile),
+    }
+    shell_profiles::save_profiles(&profiles)
+}
+
+#[tauri::command]
+async fn delete_shell_profile(name: String) -> Result<(), String> {
+    let mut profiles = shell_profiles::load_profiles()?;
+    profiles.retain(|p| p.name != name);
+    shell_profiles::save_profiles(&profiles)
+}
+
+#[tauri::command]
+async fn list_output_triggers() -> Result<Vec<TriggerRule>, String> {
+    triggers::load_rules()
+}
+
+/// Add a trigger rule, or replace the one with the same name. Takes effect in running sessions.
+#[tauri::command]
+async fn save_output_trigger(triggers: State<'_, Triggers>, rule: TriggerRule) -> Result<(), String> {
+    if rule.name.trim().is_empty() {
+        return Err("Trigger name is required".to_string());
+    }
+    let mut rules = triggers::load_rules()?;
+    match rules.iter_mut().find(|r| r.name == rule.name) {
+        Some(existing) => *existing = rule,
+        None => rules.push(rule),
+    }
+    triggers::save_rules(&rules)?;
+    triggers.reload()
+}
+
+#[tauri::command]
+async fn delete_output_trigger(triggers: State<'_, Triggers>, name: String) -> Result<(), String> {
+    let mut rules = triggers::load_rules()?;
+    rules.retain(|r| r.name != name);
+    triggers::save_rules(&rules)?;
+    triggers.reload()
+}
+
+/// What a rule, saved or not, matches in a session's scrollback.
+#[tauri::command]
+async fn test_output_trigger(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    rule: TriggerRule,
+) -> Result<Vec<TriggerMatch>, String> {
+    let (scrollback, start_offset) = {
+        let session = state.session(session_id).await?;
+        session.output.lock().scrollback.snapshot_bytes()
+    };
+    tauri::async_runtime::spawn_blocking(move || triggers::test_rule(rule, &scrollback, start_offset))
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
+}
+
 #[tauri::command]
 async fn list_files_in_dir(path: String) -> Result<Vec<String>, String> {
     tauri::async_runtime::spawn_blocking(move || {
@@ -517,9 +1855,7 @@ async fn list_files_in_dir(path: String) -> Result<Vec<String>, String> {
                 if file_type.is_dir() {
                     collect_files(&path, files, visited)?;
                 } else if file_type.is_file() {
-                    if let Some(s) = path.to_str() {
-                        files.push(s.to_string());
-                    }
+                    files.extend(path.to_str().map(str::to_string));
                 }
                 // Skip symlinks to avoid potential issues
             }
@@ -579,16 +1915,9 @@ async fn create_dir_all(path: String) -> Result<(), String> {
 #[tauri::command]
 async fn run_gh_command(cwd: String, args: Vec<String>) -> Result<String, String> {
     tauri::async_runtime::spawn_blocking(move || {
-        let path_env = std::env::var("PATH").unwrap_or_default();
-        let extended_path = format!(
-            "/opt/homebrew/bin:/usr/local/bin:/usr/bin:/bin:{}",
-            path_env
-        );
-
-        let output = Command::new("gh")
+        let output = shell_env::command("gh")
             .args(&args)
             .current_dir(&cwd)
-            .env("PATH", &extended_path)
             .output()
             .map_err(|e| format!("Failed to execute gh: {}", e))?;
 
@@ -630,6 +1959,26 @@ async fn open_in_zed(path: String) -> Result<(), String> {
     .map_err(|e| format!("Task join error: {}", e))?
 }
 
+/// Open a file a session's output refers to in Zed, at a line and column if given.
+/// Relative paths are resolved against the session's directories.
+#[tauri::command]
+async fn open_file_at_line(
+    state: State<'_, PtyState>,
+    session_id: u32,
+    path: String,
+    line: u32,
+    column: Option<u32>,
+) -> Result<(), String> {
+    let session = state.session(session_id).await?;
+    let dirs = link_dirs(&session.terminal, session.cwd.as_deref());
+    tauri::async_runtime::spawn_blocking(move || {
+        let path = file_links::resolve(&path, &dirs).ok_or_else(|| "File not found".to_string())?;
+        file_links::open_in_editor(&path, line, column)
+    })
+    .await
+    .map_err(|e| format!("Task join error: {}", e))?
+}
+
 #[derive(serde::Serialize)]
 struct SystemStats {
     cpu_usage: f32,
@@ -673,8 +2022,8 @@ async fn get_system_stats() -> Result<SystemStats, String> {
         let net_mutex = NETWORKS.get_or_init(|| std::sync::Mutex::new(Networks::new_with_refreshed_list()));
         let mut networks = net_mutex.lock().unwrap();
         networks.refresh();
-        let (total_rx, total_tx): (u64, u64) = networks.iter()
-            .map(|(_, data)| (data.total_received(), data.total_transmitted()))
+        let (total_rx, total_tx): (u64, u64) = networks.values()
+            .map(|data| (data.total_received(), data.total_transmitted()))
             .fold((0, 0), |(rx, tx), (r, t)| (rx + r, tx + t));
         drop(networks);
 
@@ -725,17 +2074,83 @@ async fn open_activity_monitor() -> Result<(), String> {
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    shell_env::resolve_in_background();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_http::init())
         .plugin(tauri_plugin_dialog::init())
+        .plugin(tauri_plugin_notification::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
         .manage(PtyState::default())
+        .manage(Notifier::load())
+        .manage(Triggers::load())
+        .manage(Playbacks::default())
+        .on_page_load(|webview, payload| {
+            // A reloading webview drops its channels without telling us, so its
+            // sessions show up as detached and the new page attaches to them again
+            if let tauri::webview::PageLoadEvent::Started = payload.event() {
+                let app = webview.app_handle().clone();
+                tauri::async_runtime::spawn(async move {
+                    let state = app.state::<PtyState>();
+                    for session in state.sessions.lock().await.values() {
+                        session.output.detach();
+                    }
+                });
+            }
+        })
+        .on_window_event(|window, event| {
+            if let tauri::WindowEvent::Focused(focused) = event {
+                let app = window.app_handle();
+                app.state::<Notifier>().window_focus_changed(app, *focused);
+            }
+        })
+        .setup(|app| {
+            watch_activity(app.handle().clone());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             create_pty_session,
             write_to_pty,
+            write_to_pty_group,
+            run_script_in_pty,
+            set_pty_group,
+            list_pty_groups,
             resize_pty,
             close_pty_session,
+            get_pty_shutdown_reports,
             get_pty_foreground_process,
+            signal_pty_foreground,
+            stop_pty_foreground,
+            get_pty_session_info,
+            get_pty_command_history,
+            get_pty_markers,
+            get_pty_scrollback,
+            get_pty_screen,
+            set_pty_clipboard_access,
+            start_pty_recording,
+            stop_pty_recording,
+            list_pty_recordings,
+            play_pty_recording,
+            set_pty_playback_speed,
+            stop_pty_playback,
+            set_pty_logging,
+            list_pty_logs,
+            open_pty_log,
+            find_pty_file_links,
+            open_file_at_line,
+            search_pty_output,
+            attach_pty_session,
+            ack_pty_output,
+            set_pty_flow_control,
+            get_pty_output_stats,
+            get_pty_exit_status,
+            list_pty_sessions,
+            get_notification_settings,
+            save_notification_settings,
+            set_pty_session_issue,
+            set_pty_notifications_muted,
+            set_focused_pty_session,
             check_path_exists,
             filter_real_files,
             run_git_command,
@@ -745,6 +2160,14 @@ pub fn run() {
             read_file,
             write_file,
             get_app_data_dir,
+            get_resolved_environment,
+            list_shell_profiles,
+            save_shell_profile,
+            delete_shell_profile,
+            list_output_triggers,
+            save_output_trigger,
+            delete_output_trigger,
+            test_output_trigger,
             list_files_in_dir,
             delete_file,
             delete_directory,
@@ -753,6 +2176,11 @@ pub fn run() {
             get_system_stats,
             open_activity_monitor
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while running tauri application")
+        .run(|app, event| {
+            if let tauri::RunEvent::Exit = event {
+                shutdown_all_sessions(app);
+            }
+        });
 }
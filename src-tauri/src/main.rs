@@ -2,5 +2,17 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    // The app re-executes itself as the background PTY host
+    #[cfg(unix)]
+    {
+        let args: Vec<String> = std::env::args().collect();
+        if args.get(1).map(String::as_str) == Some(jeonghyeon_lib::PTY_HOST_ARG) {
+            if let Some(socket_path) = args.get(2) {
+                jeonghyeon_lib::run_pty_host(std::path::Path::new(socket_path));
+            }
+            return;
+        }
+    }
+
     jeonghyeon_lib::run()
 }
@@ -0,0 +1,340 @@
+//! Desktop notifications for terminal events. They are raised from the PTY threads,
+//! so they arrive while the webview is in the background.
+//!
+//! Desktop notifications can't report clicks, so the one shown last is remembered
+//! and the next time the window gains focus the frontend is told to open its issue and tab.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+
+/// The window gaining focus this long after a notification no longer counts as clicking it.
+const ACTIVATION_WINDOW: Duration = Duration::from_secs(60);
+/// A session notifies about the same kind of event at most this often, so a noisy bell stays quiet.
+const REPEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationSettings {
+    pub enabled: bool,
+    pub bell: bool,
+    pub command_finished: bool,
+    /// Commands notify when they finish only if they ran at least this long
+    pub long_command_secs: u64,
+    pub session_exited: bool,
+    /// An agent finished its turn or is asking for approval
+    pub agent_idle: bool,
+    /// Issues whose terminals never notify
+    pub muted_issues: Vec<String>,
+}
+
+impl Default for NotificationSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            bell: true,
+            command_finished: true,
+            long_command_secs: 30,
+            session_exited: true,
+            agent_idle: true,
+            muted_issues: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+    Bell,
+    CommandFinished,
+    SessionExited,
+    AgentIdle,
+    /// A user-defined output trigger that asks for notifications
+    Trigger,
+}
+
+/// What a notification was about, sent with `notification-activated`.
+#[derive(Clone, Debug, Serialize)]
+pub struct NotificationTarget {
+    pub session_id: u32,
+    pub issue_key: Option<String>,
+    pub kind: NotificationKind,
+}
+
+#[derive(Default)]
+struct SessionEntry {
+    issue_key: Option<String>,
+    muted: bool,
+    last_sent: HashMap<NotificationKind, Instant>,
+}
+
+struct NotifierState {
+    settings: NotificationSettings,
+    /// Sessions of this app instance; closed ones are removed and never notify
+    sessions: HashMap<u32, SessionEntry>,
+    window_focused: bool,
+    /// The terminal the user is looking at, which doesn't notify while the window has focus
+    focused_session: Option<u32>,
+    last_shown: Option<(NotificationTarget, Instant)>,
+}
+
+pub struct Notifier {
+    state: Mutex<NotifierState>,
+}
+
+fn settings_path() -> Option<PathBuf> {
+    crate::app_data_dir().map(|p| p.join("notification-settings.json"))
+}
+
+pub fn load_settings() -> Result<NotificationSettings, String> {
+    let path = settings_path().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Failed to parse notification settings: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(NotificationSettings::default()),
+        Err(e) => Err(format!("Failed to read notification settings: {}", e)),
+    }
+}
+
+fn save_settings(settings: &NotificationSettings) -> Result<(), String> {
+    let path = settings_path().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(settings).map_err(|e| format!("Failed to serialize notification settings: {}", e))?;
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write notification settings: {}", e))
+}
+
+impl Notifier {
+    /// Start with the saved settings, or the defaults if there are none or they can't be read.
+    pub fn load() -> Self {
+        Self::new(load_settings().unwrap_or_default())
+    }
+
+    fn new(settings: NotificationSettings) -> Self {
+        Self {
+            state: Mutex::new(NotifierState {
+                settings,
+                sessions: HashMap::new(),
+                window_focused: true,
+                focused_session: None,
+                last_shown: None,
+            }),
+        }
+    }
+
+    pub fn settings(&self) -> NotificationSettings {
+        self.state.lock().unwrap().settings.clone()
+    }
+
+    pub fn set_settings(&self, settings: NotificationSettings) -> Result<(), String> {
+        save_settings(&settings)?;
+        self.state.lock().unwrap().settings = settings;
+        Ok(())
+    }
+
+    pub fn add_session(&self, session_id: u32) {
+        self.state.lock().unwrap().sessions.entry(session_id).or_default();
+    }
+
+    pub fn remove_session(&self, session_id: u32) {
+        self.state.lock().unwrap().sessions.remove(&session_id);
+    }
+
+    /// Also works for a PTY host session that hasn't been attached yet.
+    pub fn set_issue(&self, session_id: u32, issue_key: Option<String>) {
+        self.state.lock().unwrap().sessions.entry(session_id).or_default().issue_key = issue_key;
+    }
+
+    pub fn issue_key(&self, session_id: u32) -> Option<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .sessions
+            .get(&session_id)
+            .and_then(|entry| entry.issue_key.clone())
+    }
+
+    pub fn set_muted(&self, session_id: u32, muted: bool) {
+        self.state.lock().unwrap().sessions.entry(session_id).or_default().muted = muted;
+    }
+
+    pub fn set_focused_session(&self, session_id: Option<u32>) {
+        self.state.lock().unwrap().focused_session = session_id;
+    }
+
+    /// Minimum runtime for a finished command to notify.
+    pub fn long_command(&self) -> Duration {
+        Duration::from_secs(self.state.lock().unwrap().settings.long_command_secs)
+    }
+
+    /// Show a notification for a session unless its kind is turned off, the session or
+    /// its issue is muted, or the user is looking at it.
+    pub fn notify(&self, app: &AppHandle, session_id: u32, kind: NotificationKind, body: &str) {
+        let Some(target) = self.admit(session_id, kind) else {
+            return;
+        };
+        let title = match &target.issue_key {
+            Some(key) => format!("{} · Terminal {}", key, session_id),
+            None => format!("Terminal {}", session_id),
+        };
+        let _ = app.notification().builder().title(title).body(body).show();
+    }
+
+    /// Decide whether a notification goes out, and note it as sent if so.
+    fn admit(&self, session_id: u32, kind: NotificationKind) -> Option<NotificationTarget> {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        let settings = &state.settings;
+        let kind_enabled = match kind {
+            NotificationKind::Bell => settings.bell,
+            NotificationKind::CommandFinished => settings.command_finished,
+            NotificationKind::SessionExited => settings.session_exited,
+            NotificationKind::AgentIdle => settings.agent_idle,
+            NotificationKind::Trigger => true,
+        };
+        if !settings.enabled || !kind_enabled {
+            return None;
+        }
+        if state.window_focused && state.focused_session == Some(session_id) {
+            return None;
+        }
+        let entry = state.sessions.get_mut(&session_id)?;
+        let issue_muted = entry
+            .issue_key
+            .as_ref()
+            .is_some_and(|key| settings.muted_issues.contains(key));
+        let repeated = entry
+            .last_sent
+            .get(&kind)
+            .is_some_and(|at| at.elapsed() < REPEAT_INTERVAL);
+        if entry.muted || issue_muted || repeated {
+            return None;
+        }
+        entry.last_sent.insert(kind, Instant::now());
+
+        let target = NotificationTarget {
+            session_id,
+            issue_key: entry.issue_key.clone(),
+            kind,
+        };
+        // Clicking a notification can only be noticed if it brings the window to the front
+        if !state.window_focused {
+            state.last_shown = Some((target.clone(), Instant::now()));
+        }
+        Some(target)
+    }
+
+    /// Track window focus. Regaining it soon after a notification is taken as a click on it.
+    pub fn window_focus_changed(&self, app: &AppHandle, focused: bool) {
+        if let Some(target) = self.focus_changed(focused) {
+            let _ = app.emit("notification-activated", target);
+        }
+    }
+
+    /// The notification the window was brought to the front for, if any.
+    fn focus_changed(&self, focused: bool) -> Option<NotificationTarget> {
+        let mut state = self.state.lock().unwrap();
+        state.window_focused = focused;
+        if !focused {
+            return None;
+        }
+        state
+            .last_shown
+            .take()
+            .filter(|(_, at)| at.elapsed() < ACTIVATION_WINDOW)
+            .map(|(target, _)| target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notifier() -> Notifier {
+        let notifier = Notifier::new(NotificationSettings::default());
+        notifier.add_session(1);
+        notifier.set_issue(1, Some("APP-1".to_string()));
+        notifier
+    }
+
+    #[test]
+    fn only_open_sessions_notify() {
+        let notifier = notifier();
+        assert!(notifier.admit(2, NotificationKind::Bell).is_none());
+        notifier.remove_session(1);
+        assert!(notifier.admit(1, NotificationKind::Bell).is_none());
+    }
+
+    #[test]
+    fn settings_turn_kinds_off() {
+        let notifier = notifier();
+        notifier.state.lock().unwrap().settings.bell = false;
+        assert!(notifier.admit(1, NotificationKind::Bell).is_none());
+        assert!(notifier.admit(1, NotificationKind::SessionExited).is_some());
+        notifier.state.lock().unwrap().settings.enabled = false;
+        assert!(notifier.admit(1, NotificationKind::Trigger).is_none());
+    }
+
+    #[test]
+    fn muted_sessions_and_issues_stay_quiet() {
+        let notifier = notifier();
+        notifier.set_muted(1, true);
+        assert!(notifier.admit(1, NotificationKind::Bell).is_none());
+        notifier.set_muted(1, false);
+        notifier.state.lock().unwrap().settings.muted_issues = vec!["APP-1".to_string()];
+        assert!(notifier.admit(1, NotificationKind::Bell).is_none());
+    }
+
+    #[test]
+    fn the_terminal_in_view_only_notifies_without_focus() {
+        let notifier = notifier();
+        notifier.set_focused_session(Some(1));
+        assert!(notifier.admit(1, NotificationKind::Bell).is_none());
+        assert!(notifier.focus_changed(false).is_none());
+        assert!(notifier.admit(1, NotificationKind::Bell).is_some());
+    }
+
+    #[test]
+    fn repeats_are_held_back_per_kind() {
+        let notifier = notifier();
+        assert!(notifier.admit(1, NotificationKind::Bell).is_some());
+        assert!(notifier.admit(1, NotificationKind::Bell).is_none());
+        assert!(notifier.admit(1, NotificationKind::AgentIdle).is_some());
+    }
+
+    #[test]
+    fn focus_after_a_notification_activates_it_once() {
+        let notifier = notifier();
+        notifier.focus_changed(false);
+        notifier.admit(1, NotificationKind::CommandFinished);
+
+        let target = notifier.focus_changed(true).unwrap();
+        assert_eq!(target.session_id, 1);
+        assert_eq!(target.issue_key.as_deref(), Some("APP-1"));
+        assert_eq!(target.kind, NotificationKind::CommandFinished);
+        notifier.focus_changed(false);
+        assert!(notifier.focus_changed(true).is_none());
+    }
+
+    #[test]
+    fn notifications_shown_with_focus_are_not_activated() {
+        let notifier = notifier();
+        notifier.admit(1, NotificationKind::Bell);
+        notifier.focus_changed(false);
+        assert!(notifier.focus_changed(true).is_none());
+    }
+
+    #[test]
+    fn missing_settings_take_defaults() {
+        let settings: NotificationSettings = serde_json::from_str(r#"{"bell":false}"#).unwrap();
+        assert!(!settings.bell);
+        assert!(settings.enabled);
+        assert_eq!(settings.long_command_secs, 30);
+    }
+}
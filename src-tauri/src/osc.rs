@@ -0,0 +1,198 @@
+use crate::vt_splitter::VtState;
+
+/// OSC payloads longer than this are dropped rather than buffered.
+const MAX_OSC_LEN: usize = 1024 * 1024;
+
+/// Picks operating system commands (`ESC ] ... BEL` or `ESC ] ... ESC \`) out of
+/// PTY output. Sequences may be split across reads.
+pub struct OscScanner {
+    state: VtState,
+    payload: Vec<u8>,
+    overflowed: bool,
+    /// Saw an ESC inside an OSC, which may start the ST terminator
+    osc_escape: bool,
+    /// Saw a BEL that wasn't the end of an OSC
+    rang: bool,
+}
+
+/// A complete OSC, split at its first `;`, e.g. `7` and `file://host/path`.
+pub struct Osc {
+    pub code: String,
+    pub data: String,
+    /// Index just past the sequence in the bytes that completed it
+    pub end: usize,
+}
+
+impl OscScanner {
+    pub fn new() -> Self {
+        Self {
+            state: VtState::default(),
+            payload: Vec::new(),
+            overflowed: false,
+            osc_escape: false,
+            rang: false,
+        }
+    }
+
+    /// Feed raw output; returns the sequences completed by it, in order.
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Osc> {
+        let mut found = Vec::new();
+        for (i, &b) in bytes.iter().enumerate() {
+            let previous = self.state;
+            self.state.advance(b);
+
+            if previous.in_osc() {
+                if self.state.in_osc() {
+                    self.push(b);
+                } else if b == 0x07 {
+                    found.extend(self.take(i + 1));
+                } else if b == 0x9c && self.state.is_ground() {
+                    // The C1 form of ST, whose first byte was taken for payload
+                    self.payload.pop();
+                    found.extend(self.take(i + 1));
+                }
+            } else if self.osc_escape && b == b'\\' {
+                found.extend(self.take(i + 1));
+            } else if self.state.in_osc() {
+                self.payload.clear();
+                self.overflowed = false;
+            } else if b == 0x07 && !previous.in_string() {
+                // A BEL anywhere else is executed, even inside an escape sequence
+                self.rang = true;
+            }
+            // Any other escape, CAN or SUB aborts the OSC
+            self.osc_escape = previous.in_osc() && self.state.in_escape();
+        }
+        found
+    }
+
+    /// Whether a bell rang since the last call.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.rang)
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.payload.len() < MAX_OSC_LEN {
+            self.payload.push(b);
+        } else {
+            self.overflowed = true;
+        }
+    }
+
+    fn take(&mut self, end: usize) -> Option<Osc> {
+        let payload = std::mem::take(&mut self.payload);
+        if self.overflowed {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&payload);
+        let (code, data) = text.split_once(';').unwrap_or((&text, ""));
+        Some(Osc {
+            code: code.to_string(),
+            data: data.to_string(),
+            end,
+        })
+    }
+}
+
+/// Path from an OSC 7 report such as `file://host/Users/me/My%20Project`.
+pub fn parse_osc7_path(data: &str) -> Option<String> {
+    let rest = data.strip_prefix("file://")?;
+    // Skip the hostname; the path starts at the next slash
+    let path = &rest[rest.find('/')?..];
+    Some(percent_decode(path))
+}
+
+/// Decode `%XX` escapes, as used in OSC 7 paths and OSC 133 command lines.
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped {
+            decoded.push(byte);
+            i += 3;
+            continue;
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codes(found: &[Osc]) -> Vec<(&str, &str)> {
+        found.iter().map(|osc| (osc.code.as_str(), osc.data.as_str())).collect()
+    }
+
+    #[test]
+    fn finds_oscs_with_either_terminator() {
+        let mut scanner = OscScanner::new();
+        let bytes = b"a\x1b]7;file://host/tmp\x07b\x1b]133;A\x1b\\c";
+        let found = scanner.feed(bytes);
+        assert_eq!(codes(&found), [("7", "file://host/tmp"), ("133", "A")]);
+        assert_eq!(found[1].end, bytes.len() - 1);
+        assert!(!scanner.take_bell());
+    }
+
+    #[test]
+    fn finds_oscs_split_across_reads() {
+        let mut scanner = OscScanner::new();
+        assert!(scanner.feed(b"\x1b]133;").is_empty());
+        assert!(scanner.feed(b"D;0\x1b").is_empty());
+        let found = scanner.feed(b"\\");
+        assert_eq!(codes(&found), [("133", "D;0")]);
+        assert_eq!(found[0].end, 1);
+    }
+
+    #[test]
+    fn rings_on_bel_outside_sequences() {
+        let mut scanner = OscScanner::new();
+        scanner.feed(b"done\x07");
+        assert!(scanner.take_bell());
+        assert!(!scanner.take_bell());
+    }
+
+    #[test]
+    fn bel_inside_other_strings_is_data() {
+        let mut scanner = OscScanner::new();
+        // DCS, SOS, PM and APC, the first split across reads
+        scanner.feed(b"\x1bPq\x07");
+        scanner.feed(b"#0\x07\x1b\\");
+        scanner.feed(b"\x1bXa\x07\x1b\\\x1b^b\x07\x1b\\\x1b_c\x07\x1b\\");
+        assert!(!scanner.take_bell());
+
+        // After the string ends, a BEL rings again
+        scanner.feed(b"\x07");
+        assert!(scanner.take_bell());
+    }
+
+    #[test]
+    fn osc_after_string_is_found() {
+        let mut scanner = OscScanner::new();
+        let found = scanner.feed(b"\x1bPq\x07\x1b\\\x1b]7;file://h/x\x07");
+        assert_eq!(codes(&found), [("7", "file://h/x")]);
+        assert!(!scanner.take_bell());
+    }
+
+    #[test]
+    fn decodes_osc7_paths() {
+        assert_eq!(parse_osc7_path("file://host/Users/me/My%20Project").as_deref(), Some("/Users/me/My Project"));
+        assert_eq!(parse_osc7_path("file:///tmp").as_deref(), Some("/tmp"));
+        assert_eq!(parse_osc7_path("http://host/tmp"), None);
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+
+    #[test]
+    fn finds_oscs_in_c1_form() {
+        let mut scanner = OscScanner::new();
+        let found = scanner.feed("\u{9d}7;file://h/\u{c2}x\u{9c}".as_bytes());
+        assert_eq!(codes(&found), [("7", "file://h/\u{c2}x")]);
+    }
+}
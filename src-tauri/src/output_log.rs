@@ -0,0 +1,315 @@
+//! Opt-in logs of a session's output as plain text, for an audit trail of what ran
+//! in an issue's terminal. Logs of a worktree session go next to the worktree, in
+//! `~/.jeonghyeon/<repo>/.logs/<branch>`, so they don't show up in `git status`;
+//! logs of other sessions go to the app data dir.
+//!
+//! Each file is rotated once it reaches a size, keeping a few old ones as
+//! `.log.1`, `.log.2` and so on, and logs older than a number of days are deleted.
+
+use crate::{recording::path_safe_key, triggers::LineSplitter};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Worktrees are created under this directory in the home dir, one level per repo.
+const WORKTREES_DIR: &str = ".jeonghyeon";
+/// Logs of a repo's worktrees, inside the repo's worktree directory.
+const WORKTREE_LOGS_DIR: &str = ".logs";
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// A log is rotated once it reaches this size
+    pub max_file_bytes: u64,
+    /// Files kept per session, the current one included
+    pub max_files: usize,
+    /// Logs not written to for this many days are deleted; 0 keeps them forever
+    pub retain_days: u64,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            retain_days: 30,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LogFileInfo {
+    pub path: String,
+    pub issue_key: Option<String>,
+    /// Repo and branch of the worktree the session ran in
+    pub repo: Option<String>,
+    pub branch: Option<String>,
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub modified: u64,
+}
+
+fn worktrees_root() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(WORKTREES_DIR))
+}
+
+fn app_logs_dir() -> Option<PathBuf> {
+    crate::app_data_dir().map(|p| p.join("logs"))
+}
+
+/// Where a session started in `cwd` keeps its logs.
+pub fn log_dir(cwd: Option<&str>) -> Option<PathBuf> {
+    let worktree = cwd.zip(worktrees_root()).and_then(|(cwd, root)| {
+        let mut parts = Path::new(cwd).strip_prefix(&root).ok()?.components();
+        let repo = parts.next()?.as_os_str().to_owned();
+        let branch = parts.next()?.as_os_str().to_owned();
+        Some(root.join(repo).join(WORKTREE_LOGS_DIR).join(branch))
+    });
+    worktree.or_else(app_logs_dir)
+}
+
+/// File name of a session's log, led by its issue so logs can be listed per issue.
+pub fn log_file_name(session_id: u32, issue_key: Option<&str>) -> String {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    match issue_key.filter(|key| !key.is_empty()) {
+        Some(key) => format!("{}-session-{}-{}.log", path_safe_key(key), session_id, started),
+        None => format!("session-{}-{}.log", session_id, started),
+    }
+}
+
+fn is_log_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.contains("session-") && (name.ends_with(".log") || name.contains(".log.")))
+}
+
+/// `path.N`, the Nth older file of a log.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+fn modified_secs(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Delete logs in `dir` that haven't been written to for `retain_days`.
+fn remove_expired(dir: &Path, retain_days: u64) {
+    if retain_days == 0 {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let max_age = Duration::from_secs(retain_days * 24 * 60 * 60);
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let expired = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > max_age);
+        if expired && is_log_file(&path) {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+}
+
+/// A log file being written, line by line.
+pub struct LogWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    written: u64,
+    config: LogConfig,
+    lines: LineSplitter,
+    /// Writing stops at the first error
+    error: Option<String>,
+}
+
+impl LogWriter {
+    pub fn create(dir: &Path, file_name: &str, config: LogConfig) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+        remove_expired(dir, config.retain_days);
+        let path = dir.join(file_name);
+        let file = File::create(&path).map_err(|e| format!("Failed to create log: {}", e))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+            path,
+            written: 0,
+            config,
+            lines: LineSplitter::new(),
+            error: None,
+        })
+    }
+
+    /// Move the current file to `.log.1`, shifting older ones along, and start a new one.
+    fn rotate(&mut self) -> std::io::Result<()> {
+        self.file.flush()?;
+        let keep = self.config.max_files.saturating_sub(1);
+        if keep == 0 {
+            std::fs::remove_file(&self.path)?;
+        } else {
+            let _ = std::fs::remove_file(rotated(&self.path, keep));
+            for n in (1..keep).rev() {
+                let from = rotated(&self.path, n);
+                if from.exists() {
+                    std::fs::rename(&from, rotated(&self.path, n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated(&self.path, 1))?;
+        }
+        self.file = BufWriter::new(File::create(&self.path)?);
+        self.written = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, text: &str) -> std::io::Result<()> {
+        let len = text.len() as u64 + 1;
+        if self.written > 0 && self.written + len > self.config.max_file_bytes {
+            self.rotate()?;
+        }
+        self.file.write_all(text.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.written += len;
+        Ok(())
+    }
+
+    fn write_lines(&mut self, lines: Vec<String>) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = lines
+            .iter()
+            .try_for_each(|line| self.write_line(line))
+            .and_then(|_| self.file.flush());
+        if let Err(e) = result {
+            self.error = Some(e.to_string());
+        }
+    }
+
+    /// Log the complete lines in a chunk of raw output.
+    pub fn write(&mut self, data: &[u8]) {
+        let lines = self.lines.feed(data, 0).into_iter().map(|line| line.text).collect();
+        self.write_lines(lines);
+    }
+
+    /// Log the unfinished last line, at the end of the output.
+    pub fn finish(&mut self) {
+        let lines = self.lines.finish(0).into_iter().map(|line| line.text).collect();
+        self.write_lines(lines);
+    }
+}
+
+/// A session's log, if logging is on. Shared with the session's reader thread.
+#[derive(Default)]
+pub struct SessionLog(Mutex<Option<LogWriter>>);
+
+impl SessionLog {
+    /// Start logging to `writer`, ending any log already being written.
+    pub fn start(&self, writer: LogWriter) {
+        if let Some(mut previous) = self.0.lock().unwrap().replace(writer) {
+            previous.finish();
+        }
+    }
+
+    pub fn stop(&self) {
+        if let Some(mut writer) = self.0.lock().unwrap().take() {
+            writer.finish();
+        }
+    }
+
+    pub fn path(&self) -> Option<PathBuf> {
+        self.0.lock().unwrap().as_ref().map(|writer| writer.path.clone())
+    }
+
+    /// Why writing stopped, if it did.
+    pub fn error(&self) -> Option<String> {
+        self.0.lock().unwrap().as_ref().and_then(|writer| writer.error.clone())
+    }
+
+    pub fn write(&self, data: &[u8]) {
+        if let Some(writer) = self.0.lock().unwrap().as_mut() {
+            writer.write(data);
+        }
+    }
+}
+
+/// Directories logs may be in: the app's, and those next to each repo's worktrees.
+fn log_dirs() -> Vec<PathBuf> {
+    let subdirs = |dir: &Path| -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|p| p.is_dir()).collect())
+            .unwrap_or_default()
+    };
+    let mut dirs: Vec<PathBuf> = app_logs_dir().into_iter().collect();
+    if let Some(root) = worktrees_root() {
+        for repo in subdirs(&root) {
+            dirs.extend(subdirs(&repo.join(WORKTREE_LOGS_DIR)));
+        }
+    }
+    dirs
+}
+
+fn file_info(path: &Path) -> Option<LogFileInfo> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let name = path.file_name()?.to_str()?;
+    let issue_key = name
+        .find("session-")
+        .map(|i| name[..i].trim_end_matches('-'))
+        .filter(|key| !key.is_empty())
+        .map(str::to_string);
+
+    // `<repo>/.logs/<branch>/<file>` for worktree logs
+    let dir = path.parent()?;
+    let (repo, branch) = match dir.parent() {
+        Some(logs) if logs.file_name().is_some_and(|n| n == WORKTREE_LOGS_DIR) => (
+            logs.parent().and_then(|repo| repo.file_name()).map(|n| n.to_string_lossy().into_owned()),
+            dir.file_name().map(|n| n.to_string_lossy().into_owned()),
+        ),
+        _ => (None, None),
+    };
+
+    Some(LogFileInfo {
+        path: path.to_string_lossy().into_owned(),
+        issue_key,
+        repo,
+        branch,
+        size: metadata.len(),
+        modified: modified_secs(&metadata),
+    })
+}
+
+/// Logs of an issue, or all of them, newest first.
+pub fn list_logs(issue_key: Option<&str>) -> Vec<LogFileInfo> {
+    let mut logs: Vec<LogFileInfo> = log_dirs()
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .map(|entry| entry.path())
+        .filter(|path| is_log_file(path))
+        .filter_map(|path| file_info(&path))
+        .filter(|info| issue_key.is_none_or(|key| info.issue_key.as_deref() == Some(key)))
+        .collect();
+    logs.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.path.cmp(&b.path)));
+    logs
+}
+
+/// Whether `path` is a log this app wrote, so only those can be opened through it.
+pub fn is_known_log(path: &Path) -> bool {
+    is_log_file(path) && path.parent().is_some_and(|dir| log_dirs().iter().any(|known| known == dir))
+}
@@ -0,0 +1,68 @@
+//! Native process lookups for PTY sessions, cheap enough to poll for every open terminal.
+
+use serde::Serialize;
+use std::sync::{Mutex, OnceLock};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// Process group, where the platform has them
+    pub pgid: Option<u32>,
+    /// Executable name without path or login-shell dash, e.g. "zsh"
+    pub name: String,
+    pub cmdline: Vec<String>,
+    pub cwd: Option<String>,
+    /// Seconds since the Unix epoch
+    pub start_time: u64,
+}
+
+/// Only the processes we ask about are ever refreshed, so this stays small.
+static PROCESSES: OnceLock<Mutex<System>> = OnceLock::new();
+
+pub fn process_info(pid: u32) -> Option<ProcessInfo> {
+    if pid == 0 {
+        return None;
+    }
+    let sys_pid = Pid::from_u32(pid);
+    let refresh = ProcessRefreshKind::new()
+        .with_cmd(UpdateKind::Always)
+        .with_cwd(UpdateKind::Always)
+        .with_exe(UpdateKind::OnlyIfNotSet);
+
+    let mut sys = PROCESSES
+        .get_or_init(|| Mutex::new(System::new()))
+        .lock()
+        .unwrap();
+    // Nothing updated means the process is gone, whatever is still cached for it
+    if sys.refresh_processes_specifics(ProcessesToUpdate::Some(&[sys_pid]), refresh) == 0 {
+        return None;
+    }
+    let process = sys.process(sys_pid)?;
+
+    let name = process.name().to_string_lossy();
+    let name = name.rsplit('/').next().unwrap_or(&name);
+    Some(ProcessInfo {
+        pid,
+        pgid: process_group(pid),
+        name: name.strip_prefix('-').unwrap_or(name).to_string(),
+        cmdline: process
+            .cmd()
+            .iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect(),
+        cwd: process.cwd().map(|p| p.to_string_lossy().into_owned()),
+        start_time: process.start_time(),
+    })
+}
+
+#[cfg(unix)]
+pub fn process_group(pid: u32) -> Option<u32> {
+    let pgid = unsafe { libc::getpgid(pid as libc::pid_t) };
+    (pgid > 0).then_some(pgid as u32)
+}
+
+#[cfg(not(unix))]
+pub fn process_group(_pid: u32) -> Option<u32> {
+    None
+}
@@ -0,0 +1,233 @@
+use super::{recv_message, send_message, HostExitStatus, HostSessionInfo, Request, SpawnSpec, HOST_ARG};
+use crate::{shutdown::ShutdownReport, PtyProcess};
+use portable_pty::{Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize};
+use serde::de::DeserializeOwned;
+use std::{
+    io,
+    os::unix::{net::UnixStream, process::CommandExt},
+    path::PathBuf,
+    process::{Command, Stdio},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+/// Connection details for the PTY host. Every request opens its own connection,
+/// so the client is cheap to clone into the proxies below.
+#[derive(Clone, Debug)]
+pub struct HostClient {
+    socket_path: PathBuf,
+    first_free_id: u32,
+}
+
+impl HostClient {
+    /// Connect to the running host, starting one if needed.
+    pub fn connect_or_spawn() -> Result<Self, String> {
+        let socket_path = super::socket_path()
+            .ok_or_else(|| "Could not determine app data directory".to_string())?;
+        let mut client = Self { socket_path, first_free_id: 1 };
+        let sessions = client.list_sessions()?;
+        client.first_free_id = sessions.iter().map(|s| s.id + 1).max().unwrap_or(1);
+        Ok(client)
+    }
+
+    /// Lowest session id not taken by a session the host already owns.
+    pub fn first_free_id(&self) -> u32 {
+        self.first_free_id
+    }
+
+    fn spawn_host(&self) -> Result<(), String> {
+        let exe = std::env::current_exe().map_err(|e| format!("Failed to locate executable: {}", e))?;
+        let mut cmd = Command::new(exe);
+        cmd.arg(HOST_ARG)
+            .arg(&self.socket_path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // New session, so the host doesn't get the app's terminal signals
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::setsid();
+                Ok(())
+            });
+        }
+        let mut child = cmd.spawn().map_err(|e| format!("Failed to start PTY host: {}", e))?;
+        // Reap the host if it exits while the app is still running
+        thread::spawn(move || {
+            let _ = child.wait();
+        });
+
+        for _ in 0..100 {
+            if UnixStream::connect(&self.socket_path).is_ok() {
+                return Ok(());
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        Err("PTY host did not start".to_string())
+    }
+
+    /// Connect to the host, starting a new one if none is listening, e.g. because
+    /// the last one exited after running out of sessions.
+    fn connect(&self) -> Result<UnixStream, String> {
+        match UnixStream::connect(&self.socket_path) {
+            Err(e) if matches!(e.kind(), io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused) => {
+                // One request starts the host; the others wait for it
+                static SPAWNING: Mutex<()> = Mutex::new(());
+                let _spawning = SPAWNING.lock().unwrap_or_else(|e| e.into_inner());
+                if let Ok(stream) = UnixStream::connect(&self.socket_path) {
+                    return Ok(stream);
+                }
+                self.spawn_host()?;
+                UnixStream::connect(&self.socket_path)
+            }
+            result => result,
+        }
+        .map_err(|e| format!("Failed to reach PTY host: {}", e))
+    }
+
+    fn call<T: DeserializeOwned>(&self, request: &Request) -> Result<T, String> {
+        let mut stream = self.connect()?;
+        send_message(&mut stream, request).map_err(|e| format!("PTY host error: {}", e))?;
+        recv_message::<Result<T, String>>(&mut stream).map_err(|e| format!("PTY host error: {}", e))?
+    }
+
+    pub fn list_sessions(&self) -> Result<Vec<HostSessionInfo>, String> {
+        self.call(&Request::List)
+    }
+
+    pub fn spawn_session(
+        &self,
+        id: u32,
+        size: PtySize,
+        cmd: &CommandBuilder,
+        scrollback_limit: usize,
+    ) -> Result<HostSessionInfo, String> {
+        self.call(&Request::Spawn {
+            id,
+            rows: size.rows,
+            cols: size.cols,
+            spec: SpawnSpec::from_command(cmd),
+            scrollback_limit,
+        })
+    }
+
+    /// End a session and everything running in it, waiting up to `grace` before killing what's left.
+    pub fn shutdown_session(&self, id: u32, grace: Duration) -> Result<ShutdownReport, String> {
+        self.call(&Request::Shutdown {
+            id,
+            grace_ms: grace.as_millis() as u64,
+        })
+    }
+
+    /// Process group in the foreground of a session's terminal.
+    pub fn foreground_pgid(&self, id: u32) -> Option<libc::pid_t> {
+        self.call::<Option<libc::pid_t>>(&Request::ForegroundPgid { id }).ok().flatten()
+    }
+
+    /// Attach to a session. The returned master streams the session's buffered
+    /// output first, then live output.
+    pub fn open_session(&self, id: u32) -> Result<(HostSessionInfo, PtyProcess), String> {
+        let mut stream = self.connect()?;
+        send_message(&mut stream, &Request::Attach { id }).map_err(|e| format!("PTY host error: {}", e))?;
+        let info = recv_message::<Result<HostSessionInfo, String>>(&mut stream)
+            .map_err(|e| format!("PTY host error: {}", e))??;
+
+        let master = HostMaster {
+            client: self.clone(),
+            id,
+            stream,
+            size: Mutex::new(info.size()),
+        };
+        let child = HostChild {
+            client: self.clone(),
+            id,
+            pid: info.pid,
+        };
+        Ok((info, (Box::new(master), Box::new(child))))
+    }
+}
+
+fn to_io_error(message: String) -> io::Error {
+    io::Error::other(message)
+}
+
+/// PTY master owned by the host; reads and writes go through the attach stream.
+struct HostMaster {
+    client: HostClient,
+    id: u32,
+    stream: UnixStream,
+    size: Mutex<PtySize>,
+}
+
+impl MasterPty for HostMaster {
+    fn resize(&self, size: PtySize) -> Result<(), anyhow::Error> {
+        self.client
+            .call::<()>(&Request::Resize { id: self.id, rows: size.rows, cols: size.cols })
+            .map_err(anyhow::Error::msg)?;
+        *self.size.lock().unwrap() = size;
+        Ok(())
+    }
+
+    fn get_size(&self) -> Result<PtySize, anyhow::Error> {
+        Ok(*self.size.lock().unwrap())
+    }
+
+    fn try_clone_reader(&self) -> Result<Box<dyn io::Read + Send>, anyhow::Error> {
+        Ok(Box::new(self.stream.try_clone()?))
+    }
+
+    fn take_writer(&self) -> Result<Box<dyn io::Write + Send>, anyhow::Error> {
+        Ok(Box::new(self.stream.try_clone()?))
+    }
+
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        self.client.foreground_pgid(self.id)
+    }
+
+    fn as_raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        None
+    }
+}
+
+/// Child process owned by the host.
+#[derive(Debug)]
+struct HostChild {
+    client: HostClient,
+    id: u32,
+    pid: u32,
+}
+
+impl Child for HostChild {
+    fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.client
+            .call::<Option<HostExitStatus>>(&Request::TryWait { id: self.id })
+            .map(|status| status.as_ref().map(ExitStatus::from))
+            .map_err(to_io_error)
+    }
+
+    fn wait(&mut self) -> io::Result<ExitStatus> {
+        self.client
+            .call::<HostExitStatus>(&Request::Wait { id: self.id })
+            .map(|status| ExitStatus::from(&status))
+            .map_err(to_io_error)
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        Some(self.pid)
+    }
+}
+
+impl ChildKiller for HostChild {
+    /// Ends the session in the host: the child gets SIGHUP and the host forgets it.
+    fn kill(&mut self) -> io::Result<()> {
+        self.client.call::<()>(&Request::Kill { id: self.id }).map_err(to_io_error)
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(HostChild {
+            client: self.client.clone(),
+            id: self.id,
+            pid: self.pid,
+        })
+    }
+}
@@ -0,0 +1,240 @@
+//! Background process that owns the PTYs, so terminal sessions outlive the app.
+//!
+//! The app re-executes itself with [`HOST_ARG`] to start the host, then talks to it
+//! over a Unix socket in the app data directory. Every request is one JSON line on a
+//! fresh connection, answered with one JSON line. An `attach` request turns its
+//! connection into the session's raw byte stream: PTY output flows to the app,
+//! and anything the app writes goes to the PTY.
+
+mod client;
+mod server;
+
+pub use client::HostClient;
+pub use server::run;
+
+use portable_pty::{CommandBuilder, ExitStatus, PtySize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    io::{self, Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+};
+
+/// Command line flag that makes the binary run as the PTY host.
+pub const HOST_ARG: &str = "--pty-host";
+
+/// Bump on incompatible protocol changes. Hosts speaking an older version keep
+/// running on their own socket until their sessions end.
+const PROTOCOL_VERSION: u32 = 1;
+
+pub fn socket_path() -> Option<PathBuf> {
+    crate::app_data_dir().map(|p| p.join(format!("pty-host-v{}.sock", PROTOCOL_VERSION)))
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Spawn {
+        id: u32,
+        rows: u16,
+        cols: u16,
+        spec: SpawnSpec,
+        scrollback_limit: usize,
+    },
+    List,
+    Attach { id: u32 },
+    Resize { id: u32, rows: u16, cols: u16 },
+    Kill { id: u32 },
+    /// End the session's whole process tree and report what it took
+    Shutdown { id: u32, grace_ms: u64 },
+    Wait { id: u32 },
+    TryWait { id: u32 },
+    ForegroundPgid { id: u32 },
+}
+
+/// Everything the host needs to rebuild a `CommandBuilder`.
+/// The environment is sent in full so the child doesn't inherit the host's.
+#[derive(Serialize, Deserialize)]
+struct SpawnSpec {
+    /// Empty means the user's default shell
+    argv: Vec<String>,
+    env: Vec<(String, String)>,
+    cwd: Option<String>,
+}
+
+impl SpawnSpec {
+    fn from_command(cmd: &CommandBuilder) -> Self {
+        let argv = if cmd.is_default_prog() {
+            Vec::new()
+        } else {
+            cmd.get_argv()
+                .iter()
+                .map(|a| a.to_string_lossy().into_owned())
+                .collect()
+        };
+        Self {
+            argv,
+            env: cmd
+                .iter_full_env_as_str()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            cwd: cmd.get_cwd().map(|d| d.to_string_lossy().into_owned()),
+        }
+    }
+
+    fn to_command(&self) -> CommandBuilder {
+        let mut cmd = if self.argv.is_empty() {
+            CommandBuilder::new_default_prog()
+        } else {
+            CommandBuilder::from_argv(self.argv.iter().map(Into::into).collect())
+        };
+        cmd.env_clear();
+        for (key, value) in &self.env {
+            cmd.env(key, value);
+        }
+        if let Some(dir) = &self.cwd {
+            cmd.cwd(dir);
+        }
+        cmd
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HostSessionInfo {
+    pub id: u32,
+    pub pid: u32,
+    pub cwd: Option<String>,
+    pub rows: u16,
+    pub cols: u16,
+    pub scrollback_limit: usize,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    pub attached: bool,
+    pub exit_status: Option<HostExitStatus>,
+    /// Buffered output the host replays on attach, ahead of new output
+    #[serde(default)]
+    pub replay_bytes: u64,
+}
+
+impl HostSessionInfo {
+    fn size(&self) -> PtySize {
+        PtySize {
+            rows: self.rows,
+            cols: self.cols,
+            pixel_width: 0,
+            pixel_height: 0,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HostExitStatus {
+    pub code: u32,
+    pub signal: Option<String>,
+}
+
+impl From<&ExitStatus> for HostExitStatus {
+    fn from(status: &ExitStatus) -> Self {
+        Self {
+            code: status.exit_code(),
+            signal: crate::exit_signal(status),
+        }
+    }
+}
+
+impl From<&HostExitStatus> for ExitStatus {
+    fn from(status: &HostExitStatus) -> Self {
+        match &status.signal {
+            Some(signal) => ExitStatus::with_signal(signal),
+            None => ExitStatus::with_exit_code(status.code),
+        }
+    }
+}
+
+fn send_message<T: Serialize>(stream: &mut UnixStream, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message).map_err(io::Error::other)?;
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+/// Read one JSON line. Reads byte by byte so nothing past the newline is consumed,
+/// as raw session output may follow an `attach` response on the same stream.
+fn recv_message<T: DeserializeOwned>(stream: &mut UnixStream) -> io::Result<T> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if byte[0] == b'\n' {
+            break;
+        }
+        line.push(byte[0]);
+    }
+    serde_json::from_slice(&line).map_err(io::Error::other)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_round_trip_one_line_each() {
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        send_message(&mut a, &Request::Resize { id: 3, rows: 24, cols: 80 }).unwrap();
+        send_message(&mut a, &Request::List).unwrap();
+        match recv_message(&mut b).unwrap() {
+            Request::Resize { id, rows, cols } => assert_eq!((id, rows, cols), (3, 24, 80)),
+            _ => panic!("expected resize"),
+        }
+        assert!(matches!(recv_message(&mut b).unwrap(), Request::List));
+    }
+
+    #[test]
+    fn requests_are_tagged_by_op() {
+        let json = serde_json::to_value(Request::Attach { id: 7 }).unwrap();
+        assert_eq!(json, serde_json::json!({ "op": "attach", "id": 7 }));
+        let request: Request = serde_json::from_str(r#"{"op":"try_wait","id":2}"#).unwrap();
+        assert!(matches!(request, Request::TryWait { id: 2 }));
+    }
+
+    #[test]
+    fn recv_leaves_bytes_after_the_line_unread() {
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        a.write_all(b"{\"op\":\"kill\",\"id\":1}\nraw output").unwrap();
+        drop(a);
+        assert!(matches!(recv_message(&mut b).unwrap(), Request::Kill { id: 1 }));
+        let mut rest = Vec::new();
+        b.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"raw output");
+    }
+
+    #[test]
+    fn recv_fails_on_a_cut_line() {
+        let (mut a, mut b) = UnixStream::pair().unwrap();
+        a.write_all(b"{\"op\":\"list\"").unwrap();
+        drop(a);
+        let err = recv_message::<Request>(&mut b).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn spawn_specs_rebuild_the_command() {
+        let mut cmd = CommandBuilder::from_argv(vec!["ls".into(), "-l".into()]);
+        cmd.env_clear();
+        cmd.env("LANG", "C.UTF-8");
+        cmd.cwd("/tmp");
+        let spec = SpawnSpec::from_command(&cmd);
+        assert_eq!(spec.argv, ["ls", "-l"]);
+        assert_eq!(spec.env, [("LANG".to_string(), "C.UTF-8".to_string())]);
+        assert_eq!(spec.cwd.as_deref(), Some("/tmp"));
+
+        let rebuilt = spec.to_command();
+        assert_eq!(rebuilt.get_argv(), cmd.get_argv());
+        assert_eq!(rebuilt.get_env("LANG"), Some("C.UTF-8".as_ref()));
+
+        let shell = SpawnSpec::from_command(&CommandBuilder::new_default_prog());
+        assert!(shell.argv.is_empty());
+        assert!(shell.to_command().is_default_prog());
+    }
+}
@@ -0,0 +1,373 @@
+use super::{recv_message, send_message, HostExitStatus, HostSessionInfo, Request, SpawnSpec};
+use crate::{scrollback::Scrollback, shutdown};
+use portable_pty::{native_pty_system, ChildKiller, MasterPty, PtySize};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::{Read, Write},
+    net::Shutdown,
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// The host exits once it has had no sessions for this long.
+const IDLE_EXIT: Duration = Duration::from_secs(30);
+/// Exited sessions stay listed for this long so a relaunched app can still see how they ended.
+const EXITED_SESSION_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Output side of a session, shared between the PTY reader and attach requests.
+struct Output {
+    scrollback: Scrollback,
+    /// Attached app connection, tagged with a generation to tell reattaches apart
+    client: Option<(u64, UnixStream)>,
+    next_client: u64,
+    closed: bool,
+}
+
+type ExitSlot = Arc<(Mutex<Option<(HostExitStatus, Instant)>>, Condvar)>;
+
+struct HostSession {
+    master: Box<dyn MasterPty + Send>,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+    killer: Box<dyn ChildKiller + Send + Sync>,
+    pid: u32,
+    cwd: Option<String>,
+    size: PtySize,
+    scrollback_limit: usize,
+    created_at: u64,
+    output: Arc<Mutex<Output>>,
+    exit: ExitSlot,
+}
+
+impl HostSession {
+    fn info(&self, id: u32) -> HostSessionInfo {
+        HostSessionInfo {
+            id,
+            pid: self.pid,
+            cwd: self.cwd.clone(),
+            rows: self.size.rows,
+            cols: self.size.cols,
+            scrollback_limit: self.scrollback_limit,
+            created_at: self.created_at,
+            attached: self.output.lock().unwrap().client.is_some(),
+            exit_status: self.exit.0.lock().unwrap().as_ref().map(|(s, _)| s.clone()),
+            replay_bytes: 0,
+        }
+    }
+}
+
+struct Host {
+    sessions: Mutex<HashMap<u32, HostSession>>,
+    idle_since: Mutex<Option<Instant>>,
+}
+
+/// Serve PTY sessions on `socket_path` until idle. Returns right away if another
+/// host is already listening there.
+pub fn run(socket_path: &Path) {
+    if UnixStream::connect(socket_path).is_ok() {
+        return;
+    }
+    if let Some(parent) = socket_path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let _ = std::fs::remove_file(socket_path);
+    let listener = match UnixListener::bind(socket_path) {
+        Ok(listener) => listener,
+        Err(_) => return,
+    };
+    // Only the current user may drive our shells
+    let _ = std::fs::set_permissions(socket_path, std::fs::Permissions::from_mode(0o600));
+
+    let host = Arc::new(Host {
+        sessions: Mutex::new(HashMap::new()),
+        idle_since: Mutex::new(Some(Instant::now())),
+    });
+
+    let watchdog_host = host.clone();
+    let watchdog_path = socket_path.to_path_buf();
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(5));
+        if watchdog_host.prune() {
+            let _ = std::fs::remove_file(&watchdog_path);
+            std::process::exit(0);
+        }
+    });
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let host = host.clone();
+        thread::spawn(move || host.handle(stream));
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn respond<T: Serialize>(stream: &mut UnixStream, result: Result<T, String>) {
+    let _ = send_message(stream, &result);
+}
+
+impl Host {
+    /// Drop long-exited sessions. Returns true when the host has been idle long enough to exit.
+    fn prune(&self) -> bool {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, session| {
+            let exited_at = session.exit.0.lock().unwrap().as_ref().map(|(_, at)| *at);
+            let attached = session.output.lock().unwrap().client.is_some();
+            !matches!(exited_at, Some(at) if !attached && at.elapsed() > EXITED_SESSION_TTL)
+        });
+
+        let mut idle_since = self.idle_since.lock().unwrap();
+        if !sessions.is_empty() {
+            *idle_since = None;
+            return false;
+        }
+        let since = idle_since.get_or_insert_with(Instant::now);
+        since.elapsed() > IDLE_EXIT
+    }
+
+    fn handle(&self, mut stream: UnixStream) {
+        let request: Request = match recv_message(&mut stream) {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+
+        match request {
+            Request::Spawn { id, rows, cols, spec, scrollback_limit } => {
+                let size = PtySize { rows, cols, pixel_width: 0, pixel_height: 0 };
+                let result = self.spawn(id, size, spec, scrollback_limit);
+                respond(&mut stream, result);
+            }
+            Request::List => {
+                let sessions = self.sessions.lock().unwrap();
+                let mut list: Vec<HostSessionInfo> =
+                    sessions.iter().map(|(id, s)| s.info(*id)).collect();
+                list.sort_by_key(|s| s.id);
+                respond(&mut stream, Ok(list));
+            }
+            Request::Attach { id } => self.attach(id, stream),
+            Request::Resize { id, rows, cols } => {
+                let mut sessions = self.sessions.lock().unwrap();
+                let result = match sessions.get_mut(&id) {
+                    Some(session) => {
+                        let size = PtySize { rows, cols, pixel_width: 0, pixel_height: 0 };
+                        session.size = size;
+                        session
+                            .master
+                            .resize(size)
+                            .map_err(|e| format!("Resize error: {}", e))
+                    }
+                    None => Err("Session not found".to_string()),
+                };
+                respond(&mut stream, result);
+            }
+            Request::Kill { id } => {
+                let session = self.sessions.lock().unwrap().remove(&id);
+                if let Some(mut session) = session {
+                    let _ = session.killer.kill();
+                    if let Some((_, client)) = session.output.lock().unwrap().client.take() {
+                        let _ = client.shutdown(Shutdown::Both);
+                    }
+                }
+                respond(&mut stream, Ok(()));
+            }
+            Request::Shutdown { id, grace_ms } => {
+                let session = self.sessions.lock().unwrap().remove(&id);
+                let result = match session {
+                    Some(session) => {
+                        let grace = Duration::from_millis(grace_ms);
+                        let report = shutdown::end_process_tree(id, session.pid, grace);
+                        if let Some((_, client)) = session.output.lock().unwrap().client.take() {
+                            let _ = client.shutdown(Shutdown::Both);
+                        }
+                        Ok(report)
+                    }
+                    None => Err("Session not found".to_string()),
+                };
+                respond(&mut stream, result);
+            }
+            Request::Wait { id } => {
+                let Some(exit) = self.exit_slot(id) else {
+                    return respond::<()>(&mut stream, Err("Session not found".to_string()));
+                };
+                let (lock, cvar) = &*exit;
+                let mut status = lock.lock().unwrap();
+                while status.is_none() {
+                    status = cvar.wait(status).unwrap();
+                }
+                let result = status.as_ref().map(|(s, _)| s.clone());
+                respond(&mut stream, result.ok_or_else(|| "Session not found".to_string()));
+            }
+            Request::TryWait { id } => {
+                let result = self
+                    .exit_slot(id)
+                    .map(|exit| exit.0.lock().unwrap().as_ref().map(|(s, _)| s.clone()))
+                    .ok_or_else(|| "Session not found".to_string());
+                respond(&mut stream, result);
+            }
+            Request::ForegroundPgid { id } => {
+                let sessions = self.sessions.lock().unwrap();
+                let result = sessions
+                    .get(&id)
+                    .map(|s| s.master.process_group_leader())
+                    .ok_or_else(|| "Session not found".to_string());
+                respond(&mut stream, result);
+            }
+        }
+    }
+
+    fn exit_slot(&self, id: u32) -> Option<ExitSlot> {
+        self.sessions.lock().unwrap().get(&id).map(|s| s.exit.clone())
+    }
+
+    fn spawn(
+        &self,
+        id: u32,
+        size: PtySize,
+        spec: SpawnSpec,
+        scrollback_limit: usize,
+    ) -> Result<HostSessionInfo, String> {
+        if self.sessions.lock().unwrap().contains_key(&id) {
+            return Err(format!("Session {} already exists", id));
+        }
+
+        let pair = native_pty_system()
+            .openpty(size)
+            .map_err(|e| format!("Failed to open pty: {}", e))?;
+        let mut child = pair
+            .slave
+            .spawn_command(spec.to_command())
+            .map_err(|e| format!("Failed to spawn command: {}", e))?;
+        drop(pair.slave);
+
+        let writer = pair.master.take_writer().map_err(|e| format!("Failed to get writer: {}", e))?;
+        let mut reader = pair.master.try_clone_reader().map_err(|e| format!("Failed to get reader: {}", e))?;
+
+        let output = Arc::new(Mutex::new(Output {
+            scrollback: Scrollback::new(scrollback_limit),
+            client: None,
+            next_client: 0,
+            closed: false,
+        }));
+        let exit: ExitSlot = Arc::new((Mutex::new(None), Condvar::new()));
+
+        // Record output and forward it to the attached app, if any.
+        // A slow app blocks this thread, which in turn throttles the PTY.
+        let reader_output = output.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 8192];
+            loop {
+                let n = match reader.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => n,
+                };
+                let mut output = reader_output.lock().unwrap();
+                output.scrollback.push(&buf[..n]);
+                let failed = match output.client.as_mut() {
+                    Some((_, client)) => client.write_all(&buf[..n]).is_err(),
+                    None => false,
+                };
+                if failed {
+                    output.client = None;
+                }
+            }
+
+            let mut output = reader_output.lock().unwrap();
+            output.closed = true;
+            if let Some((_, client)) = output.client.take() {
+                let _ = client.shutdown(Shutdown::Both);
+            }
+        });
+
+        let pid = child.process_id().unwrap_or(0);
+        let killer = child.clone_killer();
+        let waiter_exit = exit.clone();
+        thread::spawn(move || {
+            let status = match child.wait() {
+                Ok(status) => HostExitStatus::from(&status),
+                Err(_) => HostExitStatus { code: 1, signal: None },
+            };
+            let (lock, cvar) = &*waiter_exit;
+            *lock.lock().unwrap() = Some((status, Instant::now()));
+            cvar.notify_all();
+        });
+
+        let session = HostSession {
+            master: pair.master,
+            writer: Arc::new(Mutex::new(writer)),
+            killer,
+            pid,
+            cwd: spec.cwd,
+            size,
+            scrollback_limit,
+            created_at: now_secs(),
+            output,
+            exit,
+        };
+
+        let info = session.info(id);
+        self.sessions.lock().unwrap().insert(id, session);
+        Ok(info)
+    }
+
+    /// Replay the session's buffered output, then pump raw bytes both ways
+    /// until the app disconnects or another attach takes over.
+    fn attach(&self, id: u32, mut stream: UnixStream) {
+        let (mut info, output, writer) = {
+            let sessions = self.sessions.lock().unwrap();
+            match sessions.get(&id) {
+                Some(s) => (s.info(id), s.output.clone(), s.writer.clone()),
+                None => return respond::<()>(&mut stream, Err("Session not found".to_string())),
+            }
+        };
+        let Ok(client) = stream.try_clone() else { return };
+
+        let generation = {
+            let mut output = output.lock().unwrap();
+            let (replay, _) = output.scrollback.snapshot_bytes();
+            info.replay_bytes = replay.len() as u64;
+            respond(&mut stream, Ok(info));
+            if stream.write_all(&replay).is_err() {
+                return;
+            }
+            if output.closed {
+                let _ = stream.shutdown(Shutdown::Both);
+                return;
+            }
+            if let Some((_, previous)) = output.client.take() {
+                let _ = previous.shutdown(Shutdown::Both);
+            }
+            let generation = output.next_client;
+            output.next_client += 1;
+            output.client = Some((generation, client));
+            generation
+        };
+
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = match stream.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            let mut writer = writer.lock().unwrap();
+            if writer.write_all(&buf[..n]).and_then(|_| writer.flush()).is_err() {
+                break;
+            }
+        }
+
+        let mut output = output.lock().unwrap();
+        if matches!(output.client, Some((g, _)) if g == generation) {
+            output.client = None;
+        }
+    }
+}
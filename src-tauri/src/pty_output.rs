@@ -0,0 +1,283 @@
+use crate::{scrollback::Scrollback, vt_splitter::DEFAULT_MAX_SEQUENCE_BYTES, PtyExit};
+use std::{
+    sync::{mpsc::{Receiver, RecvTimeoutError}, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+use tauri::ipc::{Channel, InvokeResponseBody};
+
+/// First byte of every message on a session's output channel.
+/// Output messages carry the raw bytes after the tag, the end message
+/// the child's exit as JSON.
+pub const CHANNEL_OUTPUT: u8 = 0;
+pub const CHANNEL_END: u8 = 1;
+
+/// Chunks the reader thread may queue ahead of the forwarder before it stops reading the PTY.
+pub const OUTPUT_QUEUE_CHUNKS: usize = 32;
+/// Writes that may wait for a session's writer thread before input is refused.
+pub const INPUT_QUEUE_CHUNKS: usize = 256;
+/// A frontend that acknowledges nothing for this long while output is held back
+/// is treated as gone. The channel is detached and output carries on into the
+/// scrollback only, ready for the next attach. Background webviews are throttled
+/// but still ack now and then, so this is generous.
+const ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How a session batches output and throttles itself against a slow frontend.
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct FlowConfig {
+    /// How long output is gathered before it's sent, in milliseconds
+    pub frame_ms: u64,
+    /// A batch is sent early once it reaches this size
+    pub max_batch_bytes: usize,
+    /// Sending pauses once this many bytes are unacknowledged...
+    pub high_watermark: usize,
+    /// ...and resumes when the frontend has caught up to this many
+    pub low_watermark: usize,
+    /// An unfinished escape sequence is held back until it completes, up to this size
+    pub max_sequence_bytes: usize,
+}
+
+impl Default for FlowConfig {
+    fn default() -> Self {
+        Self {
+            frame_ms: 8,
+            max_batch_bytes: 256 * 1024,
+            high_watermark: 1024 * 1024,
+            low_watermark: 256 * 1024,
+            max_sequence_bytes: DEFAULT_MAX_SEQUENCE_BYTES,
+        }
+    }
+}
+
+/// Throughput counters for a session's output, since it started in this app instance.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct OutputStats {
+    pub bytes_sent: u64,
+    pub messages_sent: u64,
+    /// Bytes sent but not yet acknowledged by the frontend
+    pub in_flight: usize,
+    /// Times sending paused waiting for acknowledgements
+    pub pauses: u64,
+    pub paused_ms: u64,
+    pub elapsed_ms: u64,
+    pub bytes_per_sec: f64,
+    pub flow: FlowConfig,
+}
+
+pub struct OutputState {
+    pub scrollback: Scrollback,
+    channel: Option<Channel<InvokeResponseBody>>,
+    /// The PTY has no more output
+    closed: bool,
+    exit: Option<PtyExit>,
+    /// Output closed and the child exited; the end message has gone out
+    pub ended: bool,
+    /// The session is shutting down, so output is no longer held back for acks
+    releasing: bool,
+    pub flow: FlowConfig,
+    in_flight: usize,
+    bytes_sent: u64,
+    messages_sent: u64,
+    pauses: u64,
+    paused: Duration,
+    started: Instant,
+}
+
+impl OutputState {
+    /// Send a tagged message, dropping the channel once the webview is gone.
+    fn send(&mut self, tag: u8, data: &[u8]) {
+        if let Some(channel) = &self.channel {
+            let mut message = Vec::with_capacity(data.len() + 1);
+            message.push(tag);
+            message.extend_from_slice(data);
+            if channel.send(InvokeResponseBody::Raw(message)).is_err() {
+                self.channel = None;
+                return;
+            }
+            self.in_flight += data.len();
+            self.bytes_sent += data.len() as u64;
+            self.messages_sent += 1;
+        }
+    }
+
+    fn send_end(&mut self) {
+        let payload = serde_json::to_vec(&self.exit).unwrap_or_default();
+        self.send(CHANNEL_END, &payload);
+        self.channel = None;
+    }
+
+    /// End the session once both its output and its child are done, in either order.
+    fn end_if_done(&mut self) {
+        if self.closed && self.exit.is_some() && !self.ended {
+            self.ended = true;
+            self.send_end();
+        }
+    }
+}
+
+/// Output state shared between a session's reader threads and the commands.
+/// Kept under one lock so attaching a channel never misses or repeats output.
+pub struct SessionOutput {
+    state: Mutex<OutputState>,
+    acked: Condvar,
+}
+
+impl SessionOutput {
+    pub fn new(scrollback_limit: usize, flow: FlowConfig) -> Self {
+        Self {
+            state: Mutex::new(OutputState {
+                scrollback: Scrollback::new(scrollback_limit),
+                channel: None,
+                closed: false,
+                exit: None,
+                ended: false,
+                releasing: false,
+                flow,
+                in_flight: 0,
+                bytes_sent: 0,
+                messages_sent: 0,
+                pauses: 0,
+                paused: Duration::ZERO,
+                started: Instant::now(),
+            }),
+            acked: Condvar::new(),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, OutputState> {
+        self.state.lock().unwrap()
+    }
+
+    /// Replace the channel and send it the scrollback, plus the end message if the session ended.
+    pub fn attach(&self, channel: Channel<InvokeResponseBody>) {
+        let mut state = self.lock();
+        state.channel = Some(channel);
+        // Acks still owed by the previous channel will never come
+        state.in_flight = 0;
+        let (scrollback, _) = state.scrollback.snapshot_bytes();
+        if !scrollback.is_empty() {
+            state.send(CHANNEL_OUTPUT, &scrollback);
+        }
+        if state.ended {
+            state.send_end();
+        }
+        self.acked.notify_all();
+    }
+
+    /// Stop sending to the current channel, e.g. because the webview that owns it reloaded.
+    /// Output keeps going into the scrollback for the next attach.
+    pub fn detach(&self) {
+        self.lock().channel = None;
+        self.acked.notify_all();
+    }
+
+    /// Whether a frontend is receiving this session's output.
+    pub fn has_channel(&self) -> bool {
+        self.lock().channel.is_some()
+    }
+
+    /// The frontend has finished rendering `bytes` of output.
+    pub fn ack(&self, bytes: usize) {
+        let mut state = self.lock();
+        state.in_flight = state.in_flight.saturating_sub(bytes);
+        self.acked.notify_all();
+    }
+
+    /// Stop holding output back for a frontend that is behind, so a shutting down
+    /// session's threads can finish.
+    pub fn release(&self) {
+        self.lock().releasing = true;
+        self.acked.notify_all();
+    }
+
+    pub fn set_flow(&self, flow: FlowConfig) {
+        self.lock().flow = flow;
+        self.acked.notify_all();
+    }
+
+    pub fn stats(&self) -> OutputStats {
+        let state = self.lock();
+        let elapsed = state.started.elapsed();
+        OutputStats {
+            bytes_sent: state.bytes_sent,
+            messages_sent: state.messages_sent,
+            in_flight: state.in_flight,
+            pauses: state.pauses,
+            paused_ms: state.paused.as_millis() as u64,
+            elapsed_ms: elapsed.as_millis() as u64,
+            bytes_per_sec: state.bytes_sent as f64 / elapsed.as_secs_f64().max(0.001),
+            flow: state.flow,
+        }
+    }
+
+    /// Record output in scrollback and send it under the same lock, waiting first
+    /// if the frontend is too far behind. A frontend that is slow to ack, like a
+    /// throttled background webview, keeps the session paused as long as acks trickle
+    /// in; one that stops acking for [`ACK_TIMEOUT`] loses its channel.
+    fn emit(&self, data: &[u8]) {
+        let mut state = self.lock();
+        if state.channel.is_some() && !state.releasing && state.in_flight > state.flow.high_watermark {
+            let paused_at = Instant::now();
+            state.pauses += 1;
+            let mut last_ack = Instant::now();
+            while state.channel.is_some() && !state.releasing && state.in_flight > state.flow.low_watermark {
+                let in_flight = state.in_flight;
+                let timeout = ACK_TIMEOUT.saturating_sub(last_ack.elapsed());
+                let (guard, wait) = self.acked.wait_timeout(state, timeout).unwrap();
+                state = guard;
+                if state.in_flight < in_flight {
+                    last_ack = Instant::now();
+                } else if wait.timed_out() {
+                    state.channel = None;
+                }
+            }
+            state.paused += paused_at.elapsed();
+        }
+        state.scrollback.push(data);
+        state.send(CHANNEL_OUTPUT, data);
+    }
+
+    fn finish(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        state.end_if_done();
+    }
+
+    pub fn set_exit(&self, exit: PtyExit) {
+        let mut state = self.lock();
+        state.exit = Some(exit);
+        state.end_if_done();
+    }
+}
+
+/// Forward chunks from a session's reader thread, batching whatever arrives within
+/// one frame into a single message. While this waits on the frontend, the bounded
+/// queue fills up and the reader stops reading, which throttles the PTY itself.
+pub fn forward(output: &SessionOutput, chunks: Receiver<Vec<u8>>) {
+    while let Ok(mut batch) = chunks.recv() {
+        let flow = output.lock().flow;
+        let deadline = Instant::now() + Duration::from_millis(flow.frame_ms);
+        let mut closed = false;
+
+        while batch.len() < flow.max_batch_bytes {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match chunks.recv_timeout(deadline - now) {
+                Ok(chunk) => batch.extend_from_slice(&chunk),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    closed = true;
+                    break;
+                }
+            }
+        }
+
+        output.emit(&batch);
+        if closed {
+            break;
+        }
+    }
+    output.finish();
+}
@@ -0,0 +1,413 @@
+//! Scripted input for a session, expect style: wait for the prompt or a pattern,
+//! send text and keys, and capture output, so setup commands run once the shell
+//! is ready instead of racing its startup.
+//!
+//! Patterns are matched against each line of output without escape sequences,
+//! the unfinished last line included, so prompts like `Password: ` match too.
+
+use crate::search;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How long a step waits when it doesn't say.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(25);
+/// Unless the shell marks where its prompt ends, output has to be quiet this long before a prompt counts.
+const PROMPT_QUIET: Duration = Duration::from_millis(300);
+/// Without shell integration, a prompt is a last line ending in one of these.
+const PROMPT_ENDINGS: &[char] = &['$', '#', '%', '>', '❯', '➜', '»'];
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScriptStep {
+    /// Wait until the shell shows a prompt, as marked by OSC 133 or by how the last line ends
+    WaitForPrompt { timeout_ms: Option<u64> },
+    /// Wait for a line matching a regular expression
+    WaitFor { pattern: String, timeout_ms: Option<u64> },
+    /// Send text as is, e.g. `"./setup.sh\n"`
+    Send { text: String },
+    /// Send named keys, e.g. `["ctrl-c", "up", "enter"]`
+    SendKeys { keys: Vec<String> },
+    /// Collect output until a line matches, e.g. the next prompt. The echo of what was sent is part of it.
+    Capture { until: String, timeout_ms: Option<u64> },
+}
+
+enum Step {
+    WaitForPrompt { timeout: Duration },
+    WaitFor { pattern: Regex, timeout: Duration },
+    Send { data: Vec<u8> },
+    Capture { until: Regex, timeout: Duration },
+}
+
+/// A script checked and ready to run.
+pub struct Script(Vec<Step>);
+
+#[derive(Debug, Default, Serialize)]
+pub struct StepResult {
+    pub index: usize,
+    pub ok: bool,
+    pub elapsed_ms: u64,
+    /// Line the step's pattern matched
+    pub matched: Option<String>,
+    /// Groups of the match; `None` for those that took no part
+    pub groups: Vec<Option<String>>,
+    /// Plain text a capture step collected
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScriptResult {
+    /// Every step ran and succeeded
+    pub success: bool,
+    /// Steps that ran, in order; the script stops at the first that fails
+    pub steps: Vec<StepResult>,
+    pub elapsed_ms: u64,
+    /// Stream offset the script read output up to
+    pub end_offset: u64,
+}
+
+/// What a script needs from the session it runs against.
+pub trait ScriptSession {
+    fn send(&self, data: &[u8]) -> Result<(), String>;
+    /// Buffered output from stream offset `from` on, and the offset it starts at
+    fn output_since(&self, from: u64) -> (Vec<u8>, u64);
+    /// Stream offset of the latest output, seen or not
+    fn output_end(&self) -> u64;
+    /// Offset output had reached when input was last sent
+    fn input_offset(&self) -> u64;
+    fn prompt_ready_at(&self) -> Option<u64>;
+    fn prompt_start_at(&self) -> Option<u64>;
+    /// Whether the shell's OSC 133 markers include `B`, the end of the prompt
+    fn marks_input(&self) -> bool;
+    fn shell_integration(&self) -> bool;
+    fn quiet_for(&self) -> Option<Duration>;
+    fn ended(&self) -> bool;
+}
+
+fn timeout(timeout_ms: Option<u64>) -> Duration {
+    timeout_ms.map_or(DEFAULT_TIMEOUT, Duration::from_millis)
+}
+
+/// Bytes a key name sends, e.g. `enter`, `up` or `ctrl-c`.
+fn key_bytes(key: &str) -> Result<Vec<u8>, String> {
+    let name = key.to_ascii_lowercase().replace('+', "-");
+    let bytes: &[u8] = match name.as_str() {
+        "enter" | "return" => b"\r",
+        "tab" => b"\t",
+        "escape" | "esc" => b"\x1b",
+        "backspace" => b"\x7f",
+        "space" => b" ",
+        "up" => b"\x1b[A",
+        "down" => b"\x1b[B",
+        "right" => b"\x1b[C",
+        "left" => b"\x1b[D",
+        "home" => b"\x1b[H",
+        "end" => b"\x1b[F",
+        "delete" => b"\x1b[3~",
+        "page_up" | "pageup" => b"\x1b[5~",
+        "page_down" | "pagedown" => b"\x1b[6~",
+        _ => {
+            return match name.strip_prefix("ctrl-").map(str::as_bytes) {
+                Some(&[c]) if c.is_ascii_lowercase() => Ok(vec![c - b'a' + 1]),
+                _ => Err(format!("Unknown key: {}", key)),
+            }
+        }
+    };
+    Ok(bytes.to_vec())
+}
+
+/// Check patterns and key names before anything is sent.
+pub fn compile(steps: Vec<ScriptStep>) -> Result<Script, String> {
+    let regex = |i: usize, pattern: &str| {
+        Regex::new(pattern).map_err(|e| format!("Invalid pattern in step {}: {}", i + 1, e))
+    };
+    steps
+        .into_iter()
+        .enumerate()
+        .map(|(i, step)| {
+            Ok(match step {
+                ScriptStep::WaitForPrompt { timeout_ms } => Step::WaitForPrompt {
+                    timeout: timeout(timeout_ms),
+                },
+                ScriptStep::WaitFor { pattern, timeout_ms } => Step::WaitFor {
+                    pattern: regex(i, &pattern)?,
+                    timeout: timeout(timeout_ms),
+                },
+                ScriptStep::Send { text } => Step::Send { data: text.into_bytes() },
+                ScriptStep::SendKeys { keys } => Step::Send {
+                    data: keys
+                        .iter()
+                        .map(|key| key_bytes(key).map_err(|e| format!("Step {}: {}", i + 1, e)))
+                        .collect::<Result<Vec<_>, _>>()?
+                        .concat(),
+                },
+                ScriptStep::Capture { until, timeout_ms } => Step::Capture {
+                    until: regex(i, &until)?,
+                    timeout: timeout(timeout_ms),
+                },
+            })
+        })
+        .collect::<Result<_, _>>()
+        .map(Script)
+}
+
+/// A line of output that matched, and the stream offset it ends at.
+struct LineMatch {
+    line: String,
+    groups: Vec<Option<String>>,
+    /// Lines before it, from where the search started
+    before: Vec<String>,
+    end: u64,
+}
+
+fn find_line(session: &impl ScriptSession, from: u64, pattern: &Regex) -> Option<LineMatch> {
+    let (bytes, start) = session.output_since(from);
+    let end = start + bytes.len() as u64;
+    let lines = search::lines(&bytes, start);
+    let i = lines.iter().position(|(line, _)| pattern.is_match(line))?;
+    let (line, _) = &lines[i];
+    let captures = pattern.captures(line)?;
+    Some(LineMatch {
+        line: line.clone(),
+        groups: captures
+            .iter()
+            .skip(1)
+            .map(|group| group.map(|g| g.as_str().to_string()))
+            .collect(),
+        before: lines[..i].iter().map(|(line, _)| line.clone()).collect(),
+        end: lines.get(i + 1).map_or(end, |(_, offset)| *offset),
+    })
+}
+
+/// Where the prompt shown after `from` ends, if the shell is waiting at one.
+fn find_prompt(session: &impl ScriptSession, from: u64) -> Option<u64> {
+    let drawing = session.quiet_for().is_some_and(|quiet| quiet < PROMPT_QUIET);
+    if session.marks_input() {
+        return session.prompt_ready_at().filter(|&at| at >= from);
+    }
+    if session.shell_integration() {
+        // Only the start is marked, so the prompt is done once output goes quiet after it
+        session.prompt_start_at().filter(|&at| at >= from)?;
+        return (!drawing).then(|| session.output_end());
+    }
+    if drawing {
+        return None;
+    }
+    let (bytes, start) = session.output_since(from);
+    let lines = search::lines(&bytes, start);
+    let (last, _) = lines.iter().rev().find(|(line, _)| !line.trim().is_empty())?;
+    last.ends_with(PROMPT_ENDINGS)
+        .then_some(start + bytes.len() as u64)
+}
+
+/// Poll `check` until it gives an answer, the session ends or `timeout` passes.
+fn wait<T>(session: &impl ScriptSession, timeout: Duration, mut check: impl FnMut() -> Option<T>) -> Result<T, String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(found) = check() {
+            return Ok(found);
+        }
+        if session.ended() {
+            return Err("Session ended".to_string());
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("Timed out after {} ms", timeout.as_millis()));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Run a script against a session, reading output from stream offset `from`,
+/// or by default from where input was last sent. Blocks until done.
+pub fn run(session: &impl ScriptSession, script: &Script, from: Option<u64>) -> ScriptResult {
+    let started = Instant::now();
+    let mut cursor = from.unwrap_or_else(|| session.input_offset());
+    let mut steps = Vec::with_capacity(script.0.len());
+
+    for (index, step) in script.0.iter().enumerate() {
+        let step_started = Instant::now();
+        let mut result = StepResult {
+            index,
+            ..Default::default()
+        };
+
+        let outcome = match step {
+            Step::WaitForPrompt { timeout } => wait(session, *timeout, || find_prompt(session, cursor)).map(|end| {
+                cursor = end;
+            }),
+            Step::WaitFor { pattern, timeout } => {
+                wait(session, *timeout, || find_line(session, cursor, pattern)).map(|found| {
+                    cursor = found.end;
+                    result.matched = Some(found.line);
+                    result.groups = found.groups;
+                })
+            }
+            Step::Send { data } => {
+                // Output already there can't be the response
+                cursor = cursor.max(session.output_end());
+                session.send(data)
+            }
+            Step::Capture { until, timeout } => wait(session, *timeout, || find_line(session, cursor, until)).map(|found| {
+                cursor = found.end;
+                result.output = Some(found.before.join("\n"));
+                result.matched = Some(found.line);
+                result.groups = found.groups;
+            }),
+        };
+
+        result.ok = outcome.is_ok();
+        result.error = outcome.err();
+        result.elapsed_ms = step_started.elapsed().as_millis() as u64;
+        let ok = result.ok;
+        steps.push(result);
+        if !ok {
+            break;
+        }
+    }
+
+    ScriptResult {
+        success: steps.len() == script.0.len() && steps.iter().all(|step| step.ok),
+        steps,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+        end_offset: cursor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct FakeSession {
+        output: Vec<u8>,
+        prompt_ready: Option<u64>,
+        prompt_start: Option<u64>,
+        marks_input: bool,
+        shell_integration: bool,
+        quiet_for: Option<Duration>,
+    }
+
+    impl ScriptSession for FakeSession {
+        fn send(&self, _data: &[u8]) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn output_since(&self, from: u64) -> (Vec<u8>, u64) {
+            let from = from.min(self.output.len() as u64);
+            (self.output[from as usize..].to_vec(), from)
+        }
+
+        fn output_end(&self) -> u64 {
+            self.output.len() as u64
+        }
+
+        fn input_offset(&self) -> u64 {
+            0
+        }
+
+        fn prompt_ready_at(&self) -> Option<u64> {
+            self.prompt_ready
+        }
+
+        fn prompt_start_at(&self) -> Option<u64> {
+            self.prompt_start
+        }
+
+        fn marks_input(&self) -> bool {
+            self.marks_input
+        }
+
+        fn shell_integration(&self) -> bool {
+            self.shell_integration
+        }
+
+        fn quiet_for(&self) -> Option<Duration> {
+            self.quiet_for
+        }
+
+        fn ended(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn key_names_to_bytes() {
+        assert_eq!(key_bytes("enter").unwrap(), b"\r");
+        assert_eq!(key_bytes("Up").unwrap(), b"\x1b[A");
+        assert_eq!(key_bytes("ctrl-c").unwrap(), [3]);
+        assert_eq!(key_bytes("Ctrl+D").unwrap(), [4]);
+        assert_eq!(key_bytes("page_down").unwrap(), b"\x1b[6~");
+        assert!(key_bytes("ctrl-1").is_err());
+        assert_eq!(key_bytes("hyper").unwrap_err(), "Unknown key: hyper");
+    }
+
+    #[test]
+    fn compile_checks_every_step() {
+        let steps = |json: &str| serde_json::from_str::<Vec<ScriptStep>>(json).unwrap();
+        assert!(compile(steps(r#"[{"type": "wait_for_prompt"}, {"type": "send_keys", "keys": ["ctrl-c", "enter"]}]"#)).is_ok());
+        let error = compile(steps(r#"[{"type": "send", "text": "x"}, {"type": "wait_for", "pattern": "("}]"#))
+            .err()
+            .unwrap();
+        assert!(error.starts_with("Invalid pattern in step 2"));
+        let error = compile(steps(r#"[{"type": "send_keys", "keys": ["nope"]}]"#)).err().unwrap();
+        assert_eq!(error, "Step 1: Unknown key: nope");
+    }
+
+    const QUIET: Option<Duration> = Some(Duration::from_secs(1));
+    const BUSY: Option<Duration> = Some(Duration::from_millis(10));
+
+    #[test]
+    fn prompt_from_input_marker() {
+        let session = FakeSession {
+            output: b"\x1b]133;A\x07~ $ \x1b]133;B\x07".to_vec(),
+            prompt_ready: Some(16),
+            prompt_start: Some(8),
+            marks_input: true,
+            shell_integration: true,
+            quiet_for: BUSY,
+        };
+        assert_eq!(find_prompt(&session, 0), Some(16));
+        assert_eq!(find_prompt(&session, 17), None);
+    }
+
+    #[test]
+    fn prompt_from_start_marker_without_input_marker() {
+        // fish sends A, C and D but never B
+        let mut session = FakeSession {
+            output: b"\x1b]133;A\x07~> ".to_vec(),
+            prompt_start: Some(8),
+            shell_integration: true,
+            quiet_for: QUIET,
+            ..Default::default()
+        };
+        assert_eq!(find_prompt(&session, 0), Some(11));
+        assert_eq!(find_prompt(&session, 9), None);
+
+        session.quiet_for = BUSY;
+        assert_eq!(find_prompt(&session, 0), None);
+
+        // Running a command clears the prompt start
+        session.prompt_start = None;
+        session.quiet_for = QUIET;
+        assert_eq!(find_prompt(&session, 0), None);
+    }
+
+    #[test]
+    fn prompt_from_last_line_without_integration() {
+        let mut session = FakeSession {
+            output: b"Last login: today\r\nuser@host:~$ ".to_vec(),
+            quiet_for: QUIET,
+            ..Default::default()
+        };
+        assert_eq!(find_prompt(&session, 0), Some(session.output.len() as u64));
+
+        session.quiet_for = BUSY;
+        assert_eq!(find_prompt(&session, 0), None);
+
+        session.quiet_for = QUIET;
+        session.output = b"compiling...\r\n".to_vec();
+        assert_eq!(find_prompt(&session, 0), None);
+    }
+}
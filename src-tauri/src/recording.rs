@@ -0,0 +1,445 @@
+//! Recording sessions to asciinema v2 `.cast` files, filed under the session's issue
+//! in the app data dir, and playing them back into a read-only terminal view.
+//!
+//! A cast is a JSON header line followed by one `[seconds, code, data]` line per event:
+//! `o` for output, `i` for input and `r` for a resize to `COLSxROWS`.
+
+use crate::pty_output::{CHANNEL_END, CHANNEL_OUTPUT};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use tauri::ipc::{Channel, InvokeResponseBody};
+
+/// Tag of a playback message carrying a resize as JSON `{cols, rows}`. Output and
+/// the end of the playback use the same tags as a session's output channel.
+pub const CHANNEL_RESIZE: u8 = 2;
+
+/// Recordings of sessions without an issue are kept here.
+const UNFILED_DIR: &str = "unfiled";
+/// Playback checks for a new speed or a stop at least this often.
+const PLAYBACK_TICK: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CastHeader {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    /// Seconds since the Unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Longer pauses are shortened to this many seconds on playback
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub env: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RecordingInfo {
+    pub path: String,
+    /// Issue the recording is filed under
+    pub issue_key: Option<String>,
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub modified: u64,
+}
+
+fn recordings_dir() -> Option<PathBuf> {
+    crate::app_data_dir().map(|p| p.join("recordings"))
+}
+
+/// Whether `path` is a recording this app made, filed under one of its issue
+/// directories, so only those can be played through it.
+pub fn is_known_recording(path: &Path) -> bool {
+    let Some(root) = recordings_dir() else {
+        return false;
+    };
+    path.extension().is_some_and(|ext| ext == "cast")
+        && path
+            .parent()
+            .filter(|dir| dir.file_name().is_some())
+            .and_then(Path::parent)
+            == Some(root.as_path())
+}
+
+/// An issue key kept to characters that are safe in a file name.
+pub fn path_safe_key(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+fn issue_dir(issue_key: Option<&str>) -> String {
+    match issue_key.filter(|key| !key.is_empty()) {
+        Some(key) => path_safe_key(key),
+        None => UNFILED_DIR.to_string(),
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+pub fn recording_info(path: &Path) -> Result<RecordingInfo, String> {
+    let metadata = std::fs::metadata(path).map_err(|e| format!("Failed to read recording: {}", e))?;
+    let issue_key = path
+        .parent()
+        .and_then(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .filter(|name| name != UNFILED_DIR);
+    Ok(RecordingInfo {
+        path: path.to_string_lossy().into_owned(),
+        issue_key,
+        size: metadata.len(),
+        modified: metadata.modified().map(unix_secs).unwrap_or(0),
+    })
+}
+
+/// Recordings filed under an issue, or all of them, newest first.
+pub fn list_recordings(issue_key: Option<&str>) -> Result<Vec<RecordingInfo>, String> {
+    let root = recordings_dir().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    let dirs: Vec<PathBuf> = match issue_key {
+        Some(key) => vec![root.join(issue_dir(Some(key)))],
+        None => match std::fs::read_dir(&root) {
+            Ok(entries) => entries.flatten().map(|entry| entry.path()).filter(|p| p.is_dir()).collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read recordings: {}", e)),
+        },
+    };
+
+    let mut recordings = Vec::new();
+    for dir in dirs {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(format!("Failed to read recordings: {}", e)),
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "cast") {
+                recordings.extend(recording_info(&path).ok());
+            }
+        }
+    }
+    recordings.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| b.path.cmp(&a.path)));
+    Ok(recordings)
+}
+
+/// An open `.cast` file being written.
+pub struct Recorder {
+    file: File,
+    path: PathBuf,
+    started: Instant,
+    record_input: bool,
+    /// Writing stops at the first error, which `stop` reports
+    error: Option<String>,
+}
+
+impl Recorder {
+    /// Create a recording for a session, filed under its issue, and write the header.
+    pub fn create(
+        session_id: u32,
+        issue_key: Option<&str>,
+        cols: u16,
+        rows: u16,
+        record_input: bool,
+    ) -> Result<Self, String> {
+        let dir = recordings_dir()
+            .ok_or_else(|| "Could not determine app data directory".to_string())?
+            .join(issue_dir(issue_key));
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+
+        let now = unix_secs(SystemTime::now());
+        let path = dir.join(format!("session-{}-{}.cast", session_id, now));
+        let mut file = File::create(&path).map_err(|e| format!("Failed to create recording: {}", e))?;
+
+        let header = CastHeader {
+            version: 2,
+            width: cols,
+            height: rows,
+            timestamp: Some(now),
+            title: Some(issue_key.map_or_else(|| format!("Session {}", session_id), str::to_string)),
+            env: HashMap::from([("TERM".to_string(), "xterm-256color".to_string())]),
+            ..Default::default()
+        };
+        let mut line = serde_json::to_vec(&header).map_err(|e| format!("Failed to serialize recording: {}", e))?;
+        line.push(b'\n');
+        file.write_all(&line).map_err(|e| format!("Failed to write recording: {}", e))?;
+
+        Ok(Self {
+            file,
+            path,
+            started: Instant::now(),
+            record_input,
+            error: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn event(&mut self, code: &str, data: &str) {
+        if self.error.is_some() {
+            return;
+        }
+        let time = self.started.elapsed().as_micros() as f64 / 1_000_000.0;
+        let Ok(mut line) = serde_json::to_vec(&(time, code, data)) else {
+            return;
+        };
+        line.push(b'\n');
+        // One write per event, so a crash leaves at most the last line cut off
+        if let Err(e) = self.file.write_all(&line) {
+            self.error = Some(e.to_string());
+        }
+    }
+}
+
+/// A session's recording, if one is running. Shared with the session's reader thread.
+#[derive(Default)]
+pub struct SessionRecording(Mutex<Option<Recorder>>);
+
+impl SessionRecording {
+    pub fn start(&self, recorder: Recorder) -> Result<(), String> {
+        let mut current = self.0.lock().unwrap();
+        if current.is_some() {
+            return Err("Session is already being recorded".to_string());
+        }
+        *current = Some(recorder);
+        Ok(())
+    }
+
+    /// Finish the recording and return where it was saved.
+    pub fn stop(&self) -> Result<PathBuf, String> {
+        let recorder = self
+            .0
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| "Session is not being recorded".to_string())?;
+        match recorder.error {
+            Some(e) => Err(format!("Failed to write recording {}: {}", recorder.path.display(), e)),
+            None => Ok(recorder.path),
+        }
+    }
+
+    pub fn path(&self) -> Option<PathBuf> {
+        self.0.lock().unwrap().as_ref().map(|recorder| recorder.path.clone())
+    }
+
+    pub fn output(&self, data: &[u8]) {
+        if let Some(recorder) = self.0.lock().unwrap().as_mut() {
+            recorder.event("o", &String::from_utf8_lossy(data));
+        }
+    }
+
+    pub fn input(&self, data: &[u8]) {
+        if let Some(recorder) = self.0.lock().unwrap().as_mut().filter(|r| r.record_input) {
+            recorder.event("i", &String::from_utf8_lossy(data));
+        }
+    }
+
+    pub fn resize(&self, cols: u16, rows: u16) {
+        if let Some(recorder) = self.0.lock().unwrap().as_mut() {
+            recorder.event("r", &format!("{}x{}", cols, rows));
+        }
+    }
+}
+
+pub struct CastEvent {
+    /// Seconds since the recording started
+    pub time: f64,
+    pub code: String,
+    pub data: String,
+}
+
+pub struct Cast {
+    pub header: CastHeader,
+    pub events: Vec<CastEvent>,
+}
+
+impl Cast {
+    pub fn duration(&self) -> Duration {
+        let end = self.events.last().map_or(0.0, |event| event.time);
+        Duration::from_secs_f64(end.max(0.0))
+    }
+}
+
+/// Read a v2 cast. A last line cut off by a crash is ignored.
+pub fn load(path: &Path) -> Result<Cast, String> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Failed to read recording: {}", e))?;
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    let header: CastHeader = lines
+        .next()
+        .ok_or_else(|| "Recording is empty".to_string())
+        .and_then(|line| serde_json::from_str(line).map_err(|e| format!("Failed to parse recording: {}", e)))?;
+    if header.version != 2 {
+        return Err(format!("Unsupported recording version: {}", header.version));
+    }
+
+    let lines: Vec<&str> = lines.collect();
+    let mut events = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        match serde_json::from_str::<(f64, String, String)>(line) {
+            Ok((time, code, data)) => events.push(CastEvent { time, code, data }),
+            Err(_) if i + 1 == lines.len() => break,
+            Err(e) => return Err(format!("Failed to parse recording: {}", e)),
+        }
+    }
+    Ok(Cast { header, events })
+}
+
+/// Speed and stop switch of a running playback.
+pub struct PlaybackControl {
+    speed: Mutex<f64>,
+    stopped: AtomicBool,
+}
+
+fn check_speed(speed: f64) -> Result<f64, String> {
+    if speed.is_finite() && speed > 0.0 {
+        Ok(speed)
+    } else {
+        Err("Playback speed must be positive".to_string())
+    }
+}
+
+impl PlaybackControl {
+    pub fn set_speed(&self, speed: f64) -> Result<(), String> {
+        *self.speed.lock().unwrap() = check_speed(speed)?;
+        Ok(())
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    fn speed(&self) -> f64 {
+        *self.speed.lock().unwrap()
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+}
+
+/// Playbacks in progress, by id.
+#[derive(Default)]
+pub struct Playbacks {
+    running: Mutex<HashMap<u32, Arc<PlaybackControl>>>,
+    next_id: AtomicU32,
+}
+
+impl Playbacks {
+    pub fn start(&self, speed: f64) -> Result<(u32, Arc<PlaybackControl>), String> {
+        let control = Arc::new(PlaybackControl {
+            speed: Mutex::new(check_speed(speed)?),
+            stopped: AtomicBool::new(false),
+        });
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        self.running.lock().unwrap().insert(id, control.clone());
+        Ok((id, control))
+    }
+
+    pub fn get(&self, playback_id: u32) -> Result<Arc<PlaybackControl>, String> {
+        self.running
+            .lock()
+            .unwrap()
+            .get(&playback_id)
+            .cloned()
+            .ok_or_else(|| "Playback not found".to_string())
+    }
+
+    pub fn finish(&self, playback_id: u32) {
+        self.running.lock().unwrap().remove(&playback_id);
+    }
+}
+
+/// `COLSxROWS` of a resize event.
+fn parse_size(data: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = data.split_once('x')?;
+    Some((cols.parse().ok()?, rows.parse().ok()?))
+}
+
+fn send(channel: &Channel<InvokeResponseBody>, tag: u8, data: &[u8]) -> bool {
+    let mut message = Vec::with_capacity(data.len() + 1);
+    message.push(tag);
+    message.extend_from_slice(data);
+    channel.send(InvokeResponseBody::Raw(message)).is_ok()
+}
+
+/// Stream a cast's output and resizes to `channel` in real time, scaled by the
+/// control's speed. Pauses longer than `idle_limit` seconds are shortened to it.
+/// Input events are skipped; the view is read-only. Blocks until done or stopped.
+pub fn play(cast: &Cast, idle_limit: Option<f64>, control: &PlaybackControl, channel: &Channel<InvokeResponseBody>) {
+    // Recording time the playback has reached, and the time of the event before
+    let mut clock = 0.0;
+    let mut target = 0.0;
+    let mut last = 0.0;
+    let mut tick = Instant::now();
+
+    for event in &cast.events {
+        let gap = (event.time - last).max(0.0);
+        last = event.time;
+        target += idle_limit.map_or(gap, |limit| gap.min(limit));
+
+        while clock < target {
+            if control.stopped() {
+                return;
+            }
+            let speed = control.speed();
+            let wait = Duration::from_secs_f64((target - clock) / speed).min(PLAYBACK_TICK);
+            std::thread::sleep(wait);
+            let now = Instant::now();
+            clock += now.duration_since(tick).as_secs_f64() * speed;
+            tick = now;
+        }
+        if control.stopped() {
+            return;
+        }
+
+        let sent = match event.code.as_str() {
+            "o" => send(channel, CHANNEL_OUTPUT, event.data.as_bytes()),
+            "r" => match parse_size(&event.data) {
+                Some((cols, rows)) => {
+                    let size = serde_json::json!({ "cols": cols, "rows": rows });
+                    send(channel, CHANNEL_RESIZE, size.to_string().as_bytes())
+                }
+                None => true,
+            },
+            _ => true,
+        };
+        // The view is gone
+        if !sent {
+            return;
+        }
+    }
+    send(channel, CHANNEL_END, b"null");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_recordings_in_issue_dirs_are_known() {
+        let root = recordings_dir().unwrap();
+        assert!(is_known_recording(&root.join("APP-1").join("session-1.cast")));
+        assert!(is_known_recording(&root.join(UNFILED_DIR).join("session-1.cast")));
+        assert!(!is_known_recording(&root.join("APP-1").join("notes.txt")));
+        assert!(!is_known_recording(&root.join("session-1.cast")));
+        assert!(!is_known_recording(&root.join("..").join("session-1.cast")));
+        assert!(!is_known_recording(&root.join("APP-1").join("..").join("..").join("session-1.cast")));
+        assert!(!is_known_recording(Path::new("/tmp/APP-1/session-1.cast")));
+    }
+}
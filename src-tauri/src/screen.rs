@@ -0,0 +1,137 @@
+//! A headless copy of each session's screen, kept by parsing its output the way
+//! the terminal view does, so the backend can tell what is on screen without a webview.
+
+use serde::Serialize;
+
+/// Serialized as a palette index, or as `[r, g, b]`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ScreenColor {
+    /// One of the 256 palette colors
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// A run of neighbouring cells in one row that share a style.
+#[derive(Debug, Serialize)]
+pub struct ScreenSpan {
+    pub text: String,
+    /// Column of the first cell
+    pub col: u16,
+    /// `None` is the terminal's default color
+    pub fg: Option<ScreenColor>,
+    pub bg: Option<ScreenColor>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl ScreenSpan {
+    fn same_style(&self, other: &ScreenSpan) -> bool {
+        (self.fg, self.bg, self.bold, self.dim, self.italic, self.underline, self.inverse)
+            == (other.fg, other.bg, other.bold, other.dim, other.italic, other.underline, other.inverse)
+    }
+
+    fn is_plain(&self) -> bool {
+        self.bg.is_none() && !self.inverse && !self.underline
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScreenSnapshot {
+    pub rows: u16,
+    pub cols: u16,
+    pub cursor_row: u16,
+    pub cursor_col: u16,
+    pub cursor_visible: bool,
+    /// Full-screen programs like vim and less draw on the alternate screen
+    pub alternate_screen: bool,
+    /// The screen as plain text, one line per row, without trailing spaces
+    pub text: String,
+    /// Styled runs of each row. Blank cells at the end of a row are left out.
+    pub spans: Vec<Vec<ScreenSpan>>,
+}
+
+pub struct TerminalScreen {
+    parser: vt100::Parser,
+}
+
+impl TerminalScreen {
+    pub fn new(rows: u16, cols: u16) -> Self {
+        // Scrolled-off lines are in the session's scrollback already
+        Self {
+            parser: vt100::Parser::new(rows.max(1), cols.max(1), 0),
+        }
+    }
+
+    pub fn process(&mut self, bytes: &[u8]) {
+        self.parser.process(bytes);
+    }
+
+    pub fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.screen_mut().set_size(rows.max(1), cols.max(1));
+    }
+
+    pub fn snapshot(&self) -> ScreenSnapshot {
+        let screen = self.parser.screen();
+        let (rows, cols) = screen.size();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        let text: Vec<String> = screen.rows(0, cols).map(|row| row.trim_end().to_string()).collect();
+
+        ScreenSnapshot {
+            rows,
+            cols,
+            cursor_row,
+            cursor_col,
+            cursor_visible: !screen.hide_cursor(),
+            alternate_screen: screen.alternate_screen(),
+            text: text.join("\n"),
+            spans: (0..rows).map(|row| row_spans(screen, row, cols)).collect(),
+        }
+    }
+}
+
+fn color(color: vt100::Color) -> Option<ScreenColor> {
+    match color {
+        vt100::Color::Default => None,
+        vt100::Color::Idx(i) => Some(ScreenColor::Indexed(i)),
+        vt100::Color::Rgb(r, g, b) => Some(ScreenColor::Rgb(r, g, b)),
+    }
+}
+
+fn row_spans(screen: &vt100::Screen, row: u16, cols: u16) -> Vec<ScreenSpan> {
+    let mut spans: Vec<ScreenSpan> = Vec::new();
+    for col in 0..cols {
+        let Some(cell) = screen.cell(row, col) else {
+            continue;
+        };
+        if cell.is_wide_continuation() {
+            continue;
+        }
+        let text = if cell.has_contents() { cell.contents() } else { " " };
+        let span = ScreenSpan {
+            text: text.to_string(),
+            col,
+            fg: color(cell.fgcolor()),
+            bg: color(cell.bgcolor()),
+            bold: cell.bold(),
+            dim: cell.dim(),
+            italic: cell.italic(),
+            underline: cell.underline(),
+            inverse: cell.inverse(),
+        };
+        match spans.last_mut() {
+            Some(last) if last.same_style(&span) => last.text.push_str(text),
+            _ => spans.push(span),
+        }
+    }
+
+    // Trailing blanks in the default style are only padding
+    if let Some(last) = spans.last_mut().filter(|span| span.is_plain()) {
+        last.text.truncate(last.text.trim_end().len());
+    }
+    spans.retain(|span| !span.text.is_empty());
+    spans
+}
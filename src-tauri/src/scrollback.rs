@@ -0,0 +1,169 @@
+use crate::vt_splitter::{VtState, DEFAULT_MAX_SEQUENCE_BYTES};
+use std::collections::VecDeque;
+
+/// Default number of output bytes kept per PTY session.
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 2 * 1024 * 1024;
+
+/// Bounded ring buffer holding the most recent output of a PTY session.
+///
+/// Offsets are positions in the session's whole output stream, so a client can
+/// tell which part of the stream a snapshot covers even after old bytes are dropped.
+pub struct Scrollback {
+    buf: VecDeque<u8>,
+    capacity: usize,
+    end_offset: u64,
+    /// Parser state at the first byte held, which may be inside whatever eviction cut in half
+    start_state: VtState,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            buf: VecDeque::with_capacity(capacity.min(64 * 1024)),
+            capacity,
+            end_offset: 0,
+            start_state: VtState::default(),
+        }
+    }
+
+    /// Append output, evicting the oldest bytes once the capacity is reached.
+    /// Returns the stream offset at which `data` starts.
+    pub fn push(&mut self, data: &[u8]) -> u64 {
+        let offset = self.end_offset;
+        self.end_offset += data.len() as u64;
+
+        if self.capacity == 0 {
+            self.buf.clear();
+            return offset;
+        }
+
+        // Only the tail of an oversized chunk can survive
+        let (dropped, kept) = data.split_at(data.len().saturating_sub(self.capacity));
+        let overflow = (self.buf.len() + kept.len()).saturating_sub(self.capacity);
+        for &b in self.buf.range(..overflow).chain(dropped) {
+            self.start_state.advance(b);
+        }
+        self.buf.drain(..overflow);
+        self.buf.extend(kept);
+        offset
+    }
+
+    /// Stream offset of the first byte still held in the buffer.
+    pub fn start_offset(&self) -> u64 {
+        self.end_offset - self.buf.len() as u64
+    }
+
+    /// Stream offset right after the last byte written.
+    pub fn end_offset(&self) -> u64 {
+        self.end_offset
+    }
+
+    /// Copy out the buffered output, starting outside any escape sequence or UTF-8 character.
+    /// Returns the bytes and the stream offset they start at.
+    pub fn snapshot_bytes(&self) -> (Vec<u8>, u64) {
+        let (front, back) = self.buf.as_slices();
+        let mut bytes = Vec::with_capacity(self.buf.len());
+        bytes.extend_from_slice(front);
+        bytes.extend_from_slice(back);
+
+        // Eviction may have cut a sequence or character in half; skip the rest of it,
+        // giving up on one that runs longer than the reader would have held back
+        let mut state = self.start_state;
+        let mut skip = 0;
+        while !state.is_ground() && skip < bytes.len().min(DEFAULT_MAX_SEQUENCE_BYTES) {
+            state.advance(bytes[skip]);
+            skip += 1;
+        }
+        bytes.drain(..skip);
+        (bytes, self.start_offset() + skip as u64)
+    }
+
+    /// Buffered output from stream offset `from` on, limited to the last `max` bytes.
+    pub fn tail(&self, from: u64, max: usize) -> Vec<u8> {
+        let from = from
+            .max(self.start_offset())
+            .max(self.end_offset.saturating_sub(max as u64))
+            .min(self.end_offset);
+        let skip = (from - self.start_offset()) as usize;
+        self.buf.range(skip..).copied().collect()
+    }
+
+    /// Like [`Scrollback::snapshot_bytes`], as text.
+    pub fn snapshot(&self) -> (String, u64) {
+        let (bytes, start) = self.snapshot_bytes();
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        };
+        (text, start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_all(scrollback: &mut Scrollback, chunks: &[&[u8]]) {
+        for chunk in chunks {
+            scrollback.push(chunk);
+        }
+    }
+
+    #[test]
+    fn keeps_the_latest_bytes() {
+        let mut scrollback = Scrollback::new(8);
+        assert_eq!(scrollback.push(b"hello "), 0);
+        assert_eq!(scrollback.push(b"world"), 6);
+        assert_eq!((scrollback.start_offset(), scrollback.end_offset()), (3, 11));
+        assert_eq!(scrollback.snapshot_bytes(), (b"lo world".to_vec(), 3));
+        assert_eq!(scrollback.tail(0, 3), b"rld");
+        assert_eq!(scrollback.tail(9, 100), b"ld");
+    }
+
+    #[test]
+    fn oversized_chunk_keeps_its_tail() {
+        let mut scrollback = Scrollback::new(4);
+        scrollback.push(b"abcdefgh");
+        assert_eq!(scrollback.snapshot_bytes(), (b"efgh".to_vec(), 4));
+    }
+
+    #[test]
+    fn disabled_scrollback_keeps_nothing() {
+        let mut scrollback = Scrollback::new(0);
+        scrollback.push(b"abc");
+        assert_eq!(scrollback.snapshot_bytes(), (Vec::new(), 3));
+    }
+
+    #[test]
+    fn wrapped_snapshot_skips_a_cut_sequence() {
+        let mut scrollback = Scrollback::new(10);
+        // The ring wraps inside the CSI
+        push_all(&mut scrollback, &[b"abc\x1b[38;5;", b"196mred"]);
+        assert_eq!(scrollback.snapshot_bytes(), (b"red".to_vec(), 14));
+
+        let mut scrollback = Scrollback::new(12);
+        // ...and inside an OSC
+        push_all(&mut scrollback, &[b"\x1b]0;a long title", b"\x07$ ok"]);
+        assert_eq!(scrollback.snapshot_bytes(), (b"$ ok".to_vec(), 17));
+    }
+
+    #[test]
+    fn wrapped_snapshot_skips_a_cut_character() {
+        let mut scrollback = Scrollback::new(4);
+        push_all(&mut scrollback, &["x한".as_bytes(), "글ab".as_bytes()]);
+        let (bytes, start) = scrollback.snapshot();
+        assert_eq!((bytes.as_str(), start), ("ab", 7));
+
+        let mut scrollback = Scrollback::new(6);
+        push_all(&mut scrollback, &["x한".as_bytes(), "글ab".as_bytes()]);
+        assert_eq!(scrollback.snapshot(), ("글ab".to_string(), 4));
+    }
+
+    #[test]
+    fn wrapped_snapshot_keeps_text_that_looks_like_a_sequence_tail() {
+        // Evicted bytes ended outside any sequence, so `1m` is text
+        let mut scrollback = Scrollback::new(4);
+        push_all(&mut scrollback, &[b"\x1b[0mab", b"1mcd"]);
+        assert_eq!(scrollback.snapshot_bytes(), (b"1mcd".to_vec(), 6));
+    }
+}
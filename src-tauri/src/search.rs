@@ -0,0 +1,158 @@
+//! Plain-text search over the scrollback the backend holds for each session,
+//! including output the terminal views have long scrolled away.
+
+use crate::ansi;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+
+/// Lines returned before and after each matching line.
+const CONTEXT_LINES: usize = 2;
+/// A search stops after this many matching lines.
+const MAX_RESULTS: usize = 1000;
+
+#[derive(Serialize)]
+pub struct SearchMatch {
+    pub session_id: u32,
+    /// Line number within the session's scrollback, from 1
+    pub line_number: usize,
+    pub line: String,
+    /// Where the query matched in `line`, in UTF-16 code units as JavaScript counts them
+    pub ranges: Vec<(usize, usize)>,
+    /// Stream offset the line starts at, as in `get_pty_scrollback`
+    pub offset: u64,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct SearchResults {
+    pub matches: Vec<SearchMatch>,
+    /// More lines matched than were returned
+    pub truncated: bool,
+}
+
+/// A session's scrollback bytes and the stream offset they start at.
+pub struct SearchTarget {
+    pub session_id: u32,
+    pub bytes: Vec<u8>,
+    pub start_offset: u64,
+}
+
+pub fn build_query(query: &str, regex: bool, case_sensitive: bool) -> Result<Regex, String> {
+    if query.is_empty() {
+        return Err("Search query is required".to_string());
+    }
+    let pattern = if regex { query.to_string() } else { regex::escape(query) };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("Invalid pattern: {}", e))
+}
+
+pub fn search(targets: &[SearchTarget], pattern: &Regex) -> SearchResults {
+    let mut matches = Vec::new();
+    for target in targets {
+        let lines = lines(&target.bytes, target.start_offset);
+        for (i, (text, offset)) in lines.iter().enumerate() {
+            let ranges: Vec<(usize, usize)> = pattern
+                .find_iter(text)
+                .filter(|m| !m.is_empty())
+                .map(|m| {
+                    let start = text[..m.start()].encode_utf16().count();
+                    (start, start + m.as_str().encode_utf16().count())
+                })
+                .collect();
+            if ranges.is_empty() {
+                continue;
+            }
+            if matches.len() == MAX_RESULTS {
+                return SearchResults { matches, truncated: true };
+            }
+            let context = |range: std::ops::Range<usize>| lines[range].iter().map(|(t, _)| t.clone()).collect();
+            matches.push(SearchMatch {
+                session_id: target.session_id,
+                line_number: i + 1,
+                line: text.clone(),
+                ranges,
+                offset: *offset,
+                before: context(i.saturating_sub(CONTEXT_LINES)..i),
+                after: context(i + 1..(i + 1 + CONTEXT_LINES).min(lines.len())),
+            });
+        }
+    }
+    SearchResults { matches, truncated: false }
+}
+
+/// Plain-text lines of raw output, each with the stream offset it starts at.
+pub fn lines(bytes: &[u8], start_offset: u64) -> Vec<(String, u64)> {
+    let mut lines = Vec::new();
+    let mut offset = start_offset;
+    for raw in bytes.split(|&b| b == b'\n') {
+        lines.push((ansi::strip_ansi(raw).trim_end().to_string(), offset));
+        offset += raw.len() as u64 + 1;
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(session_id: u32, bytes: &[u8], start_offset: u64) -> SearchTarget {
+        SearchTarget {
+            session_id,
+            bytes: bytes.to_vec(),
+            start_offset,
+        }
+    }
+
+    #[test]
+    fn splits_output_into_plain_lines() {
+        let lines = lines(b"\x1b[32mok\x1b[0m  \r\n\nlast", 10);
+        assert_eq!(lines, [("ok".to_string(), 10), (String::new(), 25), ("last".to_string(), 26)]);
+    }
+
+    #[test]
+    fn finds_matches_with_context() {
+        let targets = [
+            target(2, b"a\nb\nerror: one\nc\nd\ne", 0),
+            target(1, b"no match here", 100),
+        ];
+        let results = search(&targets, &build_query("ERROR", false, false).unwrap());
+        assert!(!results.truncated);
+        assert_eq!(results.matches.len(), 1);
+        let found = &results.matches[0];
+        assert_eq!((found.session_id, found.line_number, found.offset), (2, 3, 4));
+        assert_eq!(found.line, "error: one");
+        assert_eq!(found.ranges, [(0, 5)]);
+        assert_eq!(found.before, ["a", "b"]);
+        assert_eq!(found.after, ["c", "d"]);
+    }
+
+    #[test]
+    fn ranges_count_utf16_units() {
+        let targets = [target(1, "😀 한 fail fail".as_bytes(), 0)];
+        let results = search(&targets, &build_query("fail", false, true).unwrap());
+        assert_eq!(results.matches[0].ranges, [(5, 9), (10, 14)]);
+    }
+
+    #[test]
+    fn queries_are_literal_unless_regex() {
+        let targets = [target(1, b"a.c\nabc", 0)];
+        let literal = search(&targets, &build_query("a.c", false, false).unwrap());
+        assert_eq!(literal.matches.len(), 1);
+        let regex = search(&targets, &build_query("a.c", true, false).unwrap());
+        assert_eq!(regex.matches.len(), 2);
+        assert!(build_query("", false, false).is_err());
+        assert!(build_query("(", true, false).is_err());
+        assert!(search(&targets, &build_query("ABC", false, true).unwrap()).matches.is_empty());
+    }
+
+    #[test]
+    fn stops_at_max_results() {
+        let bytes = "x\n".repeat(MAX_RESULTS + 1);
+        let results = search(&[target(1, bytes.as_bytes(), 0)], &build_query("x", false, false).unwrap());
+        assert!(results.truncated);
+        assert_eq!(results.matches.len(), MAX_RESULTS);
+    }
+}
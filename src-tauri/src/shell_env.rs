@@ -0,0 +1,178 @@
+//! Environment of the user's login shell, for everything the app spawns.
+//!
+//! Apps started from the Dock or a desktop launcher get a bare environment, so
+//! tools installed through Homebrew, asdf, mise or nvm aren't on `PATH`. We ask
+//! the login shell for its environment once, in the background at startup, and
+//! hand the result to PTYs, git, gh and anything else we run.
+
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    process::Command,
+    sync::OnceLock,
+    time::Instant,
+};
+
+/// How long the login shell gets to print its environment.
+#[cfg(unix)]
+const RESOLVE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Variables that describe the capturing shell itself rather than the user's setup.
+#[cfg(unix)]
+const SHELL_LOCAL_VARS: &[&str] = &["_", "PWD", "OLDPWD", "SHLVL", "TERM", "COLUMNS", "LINES", "PS1"];
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EnvSource {
+    LoginShell { shell: String },
+    /// The app's own environment with common tool directories added to `PATH`
+    Fallback { reason: String },
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ResolvedEnv {
+    pub vars: HashMap<String, String>,
+    pub source: EnvSource,
+    pub resolved_in_ms: u64,
+}
+
+static RESOLVED: OnceLock<ResolvedEnv> = OnceLock::new();
+
+/// Start resolving so the first terminal doesn't have to wait for it.
+pub fn resolve_in_background() {
+    std::thread::spawn(|| {
+        resolved();
+    });
+}
+
+/// The resolved environment. Blocks until resolution finishes if it's still running.
+pub fn resolved() -> &'static ResolvedEnv {
+    RESOLVED.get_or_init(|| {
+        let started = Instant::now();
+        let (vars, source) = match capture_login_env() {
+            Ok((vars, shell)) => (vars, EnvSource::LoginShell { shell }),
+            Err(reason) => (fallback_env(), EnvSource::Fallback { reason }),
+        };
+        ResolvedEnv {
+            vars,
+            source,
+            resolved_in_ms: started.elapsed().as_millis() as u64,
+        }
+    })
+}
+
+/// A `Command` for `program` that runs with the resolved environment.
+pub fn command(program: &str) -> Command {
+    let mut cmd = Command::new(program);
+    cmd.env_clear().envs(&resolved().vars);
+    cmd
+}
+
+#[cfg(unix)]
+fn capture_login_env() -> Result<(HashMap<String, String>, String), String> {
+    use std::{io::Read, os::unix::process::CommandExt, process::Stdio};
+
+    const MARKER: &str = "__JEONGHYEON_ENV__";
+
+    let shell = portable_pty::CommandBuilder::new_default_prog().get_shell();
+    // Interactive too, since version managers are usually set up in the rc files.
+    // Markers fence off anything the rc files print themselves.
+    let script = format!("printf '{m}'; /usr/bin/env -0; printf '{m}'", m = MARKER);
+    let mut cmd = Command::new(&shell);
+    cmd.args(["-l", "-i", "-c", &script])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    // No controlling terminal, so an interactive shell can't grab ours
+    unsafe {
+        cmd.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    let mut child = cmd.spawn().map_err(|e| format!("Failed to start {}: {}", shell, e))?;
+
+    let mut stdout = child.stdout.take().ok_or_else(|| "No shell output".to_string())?;
+    let (output_tx, output_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = stdout.read_to_end(&mut output);
+        let _ = output_tx.send(output);
+    });
+
+    // The shell leads its own process group; take down anything it started
+    let pgid = child.id() as libc::pid_t;
+    let timed_out = || {
+        unsafe {
+            libc::kill(-pgid, libc::SIGKILL);
+        }
+        format!("{} did not report its environment in time", shell)
+    };
+
+    let deadline = Instant::now() + RESOLVE_TIMEOUT;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if Instant::now() < deadline => std::thread::sleep(std::time::Duration::from_millis(20)),
+            _ => {
+                let error = timed_out();
+                let _ = child.wait();
+                return Err(error);
+            }
+        }
+    }
+
+    // Something the rc files left running in the background can hold stdout open
+    let output = output_rx
+        .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        .map_err(|_| timed_out())?;
+    let output = String::from_utf8_lossy(&output);
+    let env_block = output
+        .split(MARKER)
+        .nth(1)
+        .ok_or_else(|| format!("Unexpected output from {}", shell))?;
+
+    let mut vars: HashMap<String, String> = env_block
+        .split('\0')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    for key in SHELL_LOCAL_VARS {
+        vars.remove(*key);
+    }
+    if !vars.contains_key("PATH") {
+        return Err(format!("{} reported no PATH", shell));
+    }
+    Ok((vars, shell))
+}
+
+#[cfg(not(unix))]
+fn capture_login_env() -> Result<(HashMap<String, String>, String), String> {
+    Err("Login shell environment is only resolved on Unix".to_string())
+}
+
+/// The app's environment, with the usual tool directories put first on `PATH`.
+fn fallback_env() -> HashMap<String, String> {
+    let mut vars: HashMap<String, String> = std::env::vars().collect();
+
+    #[cfg(unix)]
+    {
+        let mut entries = Vec::new();
+        if let Some(home) = dirs::home_dir() {
+            entries.push(home.join(".local/bin").to_string_lossy().into_owned());
+        }
+        if cfg!(target_os = "macos") {
+            entries.push("/opt/homebrew/bin".to_string());
+        }
+        entries.extend(["/usr/local/bin", "/usr/bin", "/bin"].map(str::to_string));
+
+        let path = vars.get("PATH").cloned().unwrap_or_default();
+        for dir in path.split(':').filter(|p| !p.is_empty()) {
+            if !entries.iter().any(|e| e == dir) {
+                entries.push(dir.to_string());
+            }
+        }
+        vars.insert("PATH".to_string(), entries.join(":"));
+    }
+    vars
+}
@@ -0,0 +1,79 @@
+//! Startup snippets that make bash, zsh and fish mark prompts and commands with
+//! OSC 133 and report their directory with OSC 7.
+//!
+//! The snippets ship inside the binary and are written to the app data directory
+//! on first use. Each shell is then started so it loads the user's own startup
+//! files first and ours last.
+
+use portable_pty::CommandBuilder;
+use std::{path::PathBuf, sync::OnceLock};
+
+const FILES: &[(&str, &str)] = &[
+    ("bash/jeonghyeon.bash", include_str!("../shell-integration/bash/jeonghyeon.bash")),
+    ("zsh/.zshenv", include_str!("../shell-integration/zsh/.zshenv")),
+    ("zsh/.zprofile", include_str!("../shell-integration/zsh/.zprofile")),
+    ("zsh/.zshrc", include_str!("../shell-integration/zsh/.zshrc")),
+    ("zsh/jeonghyeon.zsh", include_str!("../shell-integration/zsh/jeonghyeon.zsh")),
+    ("fish/jeonghyeon.fish", include_str!("../shell-integration/fish/jeonghyeon.fish")),
+];
+
+static INSTALL_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Write the snippets out, once per app run. `None` if that failed.
+fn install() -> Option<&'static PathBuf> {
+    INSTALL_DIR
+        .get_or_init(|| {
+            let dir = crate::app_data_dir()?.join("shell-integration");
+            for (name, content) in FILES {
+                let path = dir.join(name);
+                std::fs::create_dir_all(path.parent()?).ok()?;
+                if std::fs::read_to_string(&path).ok().as_deref() != Some(*content) {
+                    std::fs::write(&path, content).ok()?;
+                }
+            }
+            Some(dir)
+        })
+        .as_ref()
+}
+
+/// Turn a default-shell command into one that loads our snippet, keeping it a login
+/// shell. Leaves `cmd` alone and returns false for shells we have no snippet for.
+/// Expects the environment to be set up already, as it decides by `$SHELL`.
+pub fn integrate(cmd: &mut CommandBuilder) -> bool {
+    if !cmd.is_default_prog() {
+        return false;
+    }
+    let shell = cmd.get_shell();
+    let name = shell.rsplit('/').next().unwrap_or(&shell);
+    if !matches!(name, "bash" | "zsh" | "fish") {
+        return false;
+    }
+    let Some(dir) = install() else {
+        return false;
+    };
+    let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+
+    let args = match name {
+        "bash" => {
+            // --init-file only applies to non-login shells; the snippet loads the login files
+            cmd.env("JEONGHYEON_BASH_LOGIN", "1");
+            vec!["--init-file".to_string(), path("bash/jeonghyeon.bash")]
+        }
+        "zsh" => {
+            if let Some(user_zdotdir) = cmd.get_env("ZDOTDIR").map(|d| d.to_os_string()) {
+                cmd.env("JEONGHYEON_USER_ZDOTDIR", user_zdotdir);
+            }
+            cmd.env("ZDOTDIR", path("zsh"));
+            vec!["-l".to_string()]
+        }
+        _ => vec![
+            "-l".to_string(),
+            "--init-command".to_string(),
+            format!("source '{}'", path("fish/jeonghyeon.fish").replace('\'', "\\'")),
+        ],
+    };
+    let argv = cmd.get_argv_mut();
+    argv.push(shell.into());
+    argv.extend(args.into_iter().map(Into::into));
+    true
+}
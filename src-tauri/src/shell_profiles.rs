@@ -0,0 +1,135 @@
+use portable_pty::CommandBuilder;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, path::PathBuf};
+
+/// Locale for sessions that don't ask for one.
+/// Used when the login shell sets no `LANG` either.
+const FALLBACK_LOCALE: &str = "en_US.UTF-8";
+
+/// What to run in a new PTY. Every field is optional; unset fields fall back to
+/// the named profile, then to the user's default shell.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PtyCommand {
+    pub profile: Option<String>,
+    pub program: Option<String>,
+    pub args: Option<Vec<String>>,
+    /// Variables to set, on top of the inherited environment
+    pub env: HashMap<String, String>,
+    /// Variables to drop from the inherited environment
+    pub env_remove: Vec<String>,
+    /// e.g. `ko_KR.UTF-8`; applied to `LANG` and `LC_ALL`
+    pub locale: Option<String>,
+    /// Load our OSC 133 snippet into bash, zsh or fish; on unless set to false
+    pub shell_integration: Option<bool>,
+}
+
+/// A named, saved [`PtyCommand`], e.g. "claude" or "nix develop".
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShellProfile {
+    pub name: String,
+    pub program: Option<String>,
+    pub args: Vec<String>,
+    pub env: HashMap<String, String>,
+    pub env_remove: Vec<String>,
+    pub locale: Option<String>,
+}
+
+fn profiles_path() -> Option<PathBuf> {
+    crate::app_data_dir().map(|p| p.join("shell-profiles.json"))
+}
+
+pub fn load_profiles() -> Result<Vec<ShellProfile>, String> {
+    let path = profiles_path().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Failed to parse shell profiles: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read shell profiles: {}", e)),
+    }
+}
+
+pub fn save_profiles(profiles: &[ShellProfile]) -> Result<(), String> {
+    let path = profiles_path().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(profiles).map_err(|e| format!("Failed to serialize shell profiles: {}", e))?;
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write shell profiles: {}", e))
+}
+
+impl PtyCommand {
+    /// Fill unset fields from the named profile. Explicit values win; env maps are merged.
+    fn with_profile(mut self) -> Result<Self, String> {
+        let Some(name) = &self.profile else {
+            return Ok(self);
+        };
+        let profile = load_profiles()?
+            .into_iter()
+            .find(|p| &p.name == name)
+            .ok_or_else(|| format!("Shell profile not found: {}", name))?;
+
+        if self.program.is_none() {
+            self.program = profile.program;
+            if self.args.is_none() {
+                self.args = Some(profile.args);
+            }
+        }
+        for (key, value) in profile.env {
+            self.env.entry(key).or_insert(value);
+        }
+        self.env_remove.extend(profile.env_remove);
+        self.locale = self.locale.or(profile.locale);
+        Ok(self)
+    }
+
+    /// Build the command to spawn. Without a program this is the user's default shell.
+    pub fn build(self, cwd: Option<&str>) -> Result<CommandBuilder, String> {
+        let spec = self.with_profile()?;
+
+        let mut cmd = match &spec.program {
+            Some(program) => {
+                let mut cmd = CommandBuilder::new(program);
+                cmd.args(spec.args.iter().flatten());
+                cmd
+            }
+            None => CommandBuilder::new_default_prog(),
+        };
+        if let Some(dir) = cwd {
+            cmd.cwd(dir);
+        }
+
+        // Start from the login shell's environment, not the app's
+        cmd.env_clear();
+        for (key, value) in &crate::shell_env::resolved().vars {
+            cmd.env(key, value);
+        }
+        for key in &spec.env_remove {
+            cmd.env_remove(key);
+        }
+        // Set TERM for proper escape sequence handling
+        cmd.env("TERM", "xterm-256color");
+
+        // Keep the user's own locale unless one was asked for; only fall back to
+        // UTF-8 English when there's none, for proper Korean/CJK character handling
+        match &spec.locale {
+            Some(locale) => {
+                cmd.env("LANG", locale);
+                cmd.env("LC_ALL", locale);
+            }
+            None => {
+                if cmd.get_env("LANG").is_none_or(|lang| lang.is_empty()) {
+                    cmd.env("LANG", FALLBACK_LOCALE);
+                }
+            }
+        }
+
+        for (key, value) in &spec.env {
+            cmd.env(key, value);
+        }
+        if spec.shell_integration.unwrap_or(true) {
+            crate::shell_integration::integrate(&mut cmd);
+        }
+        Ok(cmd)
+    }
+}
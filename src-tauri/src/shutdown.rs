@@ -0,0 +1,220 @@
+//! Ending a session for good: its shell and everything started from it, background
+//! jobs like dev servers included, so nothing is left holding a port after a tab closes.
+//! Used by the app for its own sessions and by the PTY host for the ones it keeps.
+
+use crate::{
+    process_info::process_group,
+    signals::{self, PtySignal},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessStatus, ProcessesToUpdate, System, UpdateKind};
+
+/// How long processes get to exit after SIGHUP before they are killed.
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+/// How long killed processes get to disappear before they count as survivors.
+const KILL_WAIT: Duration = Duration::from_millis(500);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A process that outlived the grace period.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KilledProcess {
+    pub pid: u32,
+    pub name: String,
+    pub cmdline: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    pub session_id: u32,
+    /// Processes in the session's tree when shutdown began, the shell included
+    pub processes: usize,
+    /// Process groups sent SIGHUP: the shell's and one per job
+    pub hung_up: Vec<u32>,
+    /// Processes still running after the grace period, killed with SIGKILL
+    pub killed: Vec<KilledProcess>,
+    /// Processes that outlived even SIGKILL, e.g. stuck in uninterruptible sleep
+    pub survivors: Vec<u32>,
+    /// The shell was reaped and the session's threads finished
+    pub reaped: bool,
+    pub elapsed_ms: u64,
+}
+
+struct TreeProcess {
+    pid: Pid,
+    /// Tells the process apart from a later one that reuses its pid
+    start_time: u64,
+    pgid: Option<u32>,
+    name: String,
+    cmdline: Vec<String>,
+}
+
+/// `root` and all its descendants, found through parent links.
+fn process_tree(root: u32) -> Vec<TreeProcess> {
+    let mut sys = System::new();
+    sys.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        ProcessRefreshKind::new().with_cmd(UpdateKind::Always),
+    );
+
+    let mut children: HashMap<Pid, Vec<Pid>> = HashMap::new();
+    for (pid, process) in sys.processes() {
+        // Threads are listed as processes on Linux
+        if process.thread_kind().is_some() {
+            continue;
+        }
+        if let Some(parent) = process.parent() {
+            children.entry(parent).or_default().push(*pid);
+        }
+    }
+
+    let mut tree = Vec::new();
+    let mut pending = vec![Pid::from_u32(root)];
+    while let Some(pid) = pending.pop() {
+        let Some(process) = sys.process(pid) else {
+            continue;
+        };
+        tree.push(TreeProcess {
+            pid,
+            start_time: process.start_time(),
+            pgid: process_group(pid.as_u32()),
+            name: process.name().to_string_lossy().into_owned(),
+            cmdline: process
+                .cmd()
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect(),
+        });
+        pending.extend(children.remove(&pid).unwrap_or_default());
+    }
+    tree
+}
+
+/// Processes of the tree that are still running. Zombies are done; their parent just hasn't reaped them.
+fn running(tree: &[TreeProcess]) -> Vec<&TreeProcess> {
+    // A fresh list each time, as refreshing keeps entries for processes that are gone
+    let mut sys = System::new();
+    let pids: Vec<Pid> = tree.iter().map(|p| p.pid).collect();
+    sys.refresh_processes_specifics(ProcessesToUpdate::Some(&pids), ProcessRefreshKind::new());
+    tree.iter()
+        .filter(|p| {
+            sys.process(p.pid).is_some_and(|process| {
+                process.start_time() == p.start_time && process.status() != ProcessStatus::Zombie
+            })
+        })
+        .collect()
+}
+
+#[cfg(unix)]
+fn kill(pid: u32) -> bool {
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) == 0 }
+}
+
+#[cfg(not(unix))]
+fn kill(pid: u32) -> bool {
+    let mut sys = System::new();
+    let pid = Pid::from_u32(pid);
+    sys.refresh_processes_specifics(ProcessesToUpdate::Some(&[pid]), ProcessRefreshKind::new());
+    sys.process(pid).is_some_and(|process| process.kill())
+}
+
+fn wait_until_gone(tree: &[TreeProcess], timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    while !running(tree).is_empty() && Instant::now() < deadline {
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Hang up every process group in the tree under `root_pid`, as closing a terminal
+/// would, then kill whatever is still running after `grace`. Blocks until done.
+pub fn end_process_tree(session_id: u32, root_pid: u32, grace: Duration) -> ShutdownReport {
+    let started = Instant::now();
+    let tree = process_tree(root_pid);
+
+    let own_group = process_group(std::process::id());
+    let mut groups: Vec<u32> = tree
+        .iter()
+        .filter_map(|p| p.pgid)
+        .filter(|&pgid| Some(pgid) != own_group)
+        .collect();
+    groups.sort_unstable();
+    groups.dedup();
+    let hung_up = groups
+        .into_iter()
+        .filter(|&pgid| signals::send(pgid, PtySignal::Sighup).is_ok())
+        .collect();
+
+    wait_until_gone(&tree, grace);
+
+    let killed: Vec<KilledProcess> = running(&tree)
+        .into_iter()
+        .filter(|p| kill(p.pid.as_u32()))
+        .map(|p| KilledProcess {
+            pid: p.pid.as_u32(),
+            name: p.name.clone(),
+            cmdline: p.cmdline.clone(),
+        })
+        .collect();
+    if !killed.is_empty() {
+        wait_until_gone(&tree, KILL_WAIT);
+    }
+    let survivors = running(&tree).iter().map(|p| p.pid.as_u32()).collect();
+
+    ShutdownReport {
+        session_id,
+        processes: tree.len(),
+        hung_up,
+        killed,
+        survivors,
+        reaped: false,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+
+    /// Start `script` in its own process group, as a PTY shell would be, and wait for its `sleep`.
+    fn start(script: &str) -> Child {
+        let child = Command::new("sh")
+            .args(["-c", script])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !process_tree(child.id()).iter().any(|p| p.name == "sleep") {
+            assert!(Instant::now() < deadline, "sleep never started");
+            std::thread::sleep(POLL_INTERVAL);
+        }
+        child
+    }
+
+    #[test]
+    fn hangs_up_the_whole_tree() {
+        let mut child = start("sleep 30 & sleep 30");
+        let report = end_process_tree(1, child.id(), SHUTDOWN_GRACE);
+        child.wait().unwrap();
+
+        assert_eq!(report.session_id, 1);
+        assert!(report.processes >= 2, "{:?}", report);
+        assert_eq!(report.hung_up, [child.id()]);
+        assert!(report.killed.is_empty(), "{:?}", report);
+        assert!(report.survivors.is_empty(), "{:?}", report);
+    }
+
+    #[test]
+    fn kills_what_ignores_the_hangup() {
+        let mut child = start("trap '' HUP; sleep 30; true");
+        let report = end_process_tree(2, child.id(), Duration::from_millis(200));
+        child.wait().unwrap();
+
+        assert!(report.killed.iter().any(|p| p.name == "sleep"), "{:?}", report);
+        assert!(report.survivors.is_empty(), "{:?}", report);
+    }
+}
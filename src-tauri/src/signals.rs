@@ -0,0 +1,96 @@
+//! Signals for the job in the foreground of a session, sent to its whole process
+//! group the way the terminal sends SIGINT for Ctrl-C, so the shell itself is left alone.
+
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// How long a stop waits after each signal before escalating to the next.
+pub const STOP_GRACE: Duration = Duration::from_secs(3);
+/// Signals a stop sends in turn until the job is gone.
+const STOP_SEQUENCE: [PtySignal; 3] = [PtySignal::Sigint, PtySignal::Sigterm, PtySignal::Sigkill];
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PtySignal {
+    Sighup,
+    Sigint,
+    Sigquit,
+    Sigkill,
+    Sigterm,
+    Sigstop,
+    Sigcont,
+}
+
+#[cfg(unix)]
+impl PtySignal {
+    fn number(self) -> libc::c_int {
+        match self {
+            PtySignal::Sighup => libc::SIGHUP,
+            PtySignal::Sigint => libc::SIGINT,
+            PtySignal::Sigquit => libc::SIGQUIT,
+            PtySignal::Sigkill => libc::SIGKILL,
+            PtySignal::Sigterm => libc::SIGTERM,
+            PtySignal::Sigstop => libc::SIGSTOP,
+            PtySignal::Sigcont => libc::SIGCONT,
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn send(pgid: u32, signal: PtySignal) -> Result<(), String> {
+    if unsafe { libc::kill(-(pgid as libc::pid_t), signal.number()) } == 0 {
+        Ok(())
+    } else {
+        let name = format!("{:?}", signal).to_uppercase();
+        Err(format!("Failed to send {}: {}", name, std::io::Error::last_os_error()))
+    }
+}
+
+#[cfg(not(unix))]
+pub fn send(_pgid: u32, _signal: PtySignal) -> Result<(), String> {
+    Err("Signals are not supported on this platform".to_string())
+}
+
+#[cfg(unix)]
+fn group_exists(pgid: u32) -> bool {
+    unsafe { libc::kill(-(pgid as libc::pid_t), 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn group_exists(_pgid: u32) -> bool {
+    false
+}
+
+/// Wait up to `timeout` for every process in the group to exit.
+fn wait_gone(pgid: u32, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while group_exists(pgid) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    true
+}
+
+/// Interrupt a process group, escalating to SIGTERM and then SIGKILL if it is still
+/// running `grace` after each. Returns the signal it gave in to. Blocks while waiting.
+pub fn stop(pgid: u32, grace: Duration) -> Result<PtySignal, String> {
+    let mut sent = None;
+    for signal in STOP_SEQUENCE {
+        if let Err(e) = send(pgid, signal) {
+            // It may have exited just after the last check
+            return sent.filter(|_| !group_exists(pgid)).ok_or(e);
+        }
+        sent = Some(signal);
+        // A stopped job only sees SIGINT and SIGTERM once it runs again
+        if signal != PtySignal::Sigkill {
+            let _ = send(pgid, PtySignal::Sigcont);
+        }
+        if wait_gone(pgid, grace) {
+            return Ok(signal);
+        }
+    }
+    Err(format!("Process group {} is still running", pgid))
+}
@@ -0,0 +1,306 @@
+use crate::{
+    activity::{ActivityState, ECHO_WINDOW},
+    ansi, osc,
+    triggers::TriggerMatch,
+};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Finished commands kept per session.
+const MAX_COMMAND_HISTORY: usize = 500;
+/// Trigger markers kept per session.
+const MAX_MARKERS: usize = 1000;
+/// Echoed input kept while waiting for a command to start, for shells that don't send its text.
+const MAX_INPUT_ECHO: usize = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CwdSource {
+    /// The directory the session was started in
+    Initial,
+    /// Reported by the shell with OSC 7
+    Osc7,
+    /// Read from the foreground process, for shells that don't report it
+    Process,
+}
+
+/// One command run at a shell prompt, as marked by OSC 133.
+/// Offsets are positions in the session's output stream, like scrollback offsets.
+#[derive(Clone, Debug, Serialize)]
+pub struct CommandRecord {
+    /// Sequence number within the session
+    pub id: u64,
+    pub command: Option<String>,
+    pub cwd: Option<String>,
+    /// Milliseconds since the Unix epoch
+    pub started_at: u64,
+    pub duration_ms: Option<u64>,
+    /// `None` while running, or if the shell didn't report it
+    pub exit_code: Option<i32>,
+    /// Where the prompt the command was typed at starts
+    pub prompt_offset: Option<u64>,
+    /// Where the command's output starts
+    pub output_offset: u64,
+    pub end_offset: Option<u64>,
+}
+
+pub enum TerminalEvent {
+    CwdChanged { cwd: String, source: CwdSource },
+    CommandStarted(CommandRecord),
+    CommandFinished(CommandRecord),
+    Bell,
+    ActivityChanged {
+        state: ActivityState,
+        previous: ActivityState,
+        agent: Option<&'static str>,
+    },
+}
+
+/// What a session's output and processes have told us about the terminal,
+/// shared between its reader thread and the commands.
+pub struct TerminalState {
+    pub cwd: Option<String>,
+    pub cwd_source: CwdSource,
+    commands: VecDeque<CommandRecord>,
+    running: Option<(CommandRecord, Instant)>,
+    prompt_offset: Option<u64>,
+    /// Where the shell became ready for a command, while it still is
+    prompt_ready: Option<u64>,
+    /// The shell marks its prompts with OSC 133
+    pub shell_integration: bool,
+    /// The shell marks where input starts with `B` too; fish only marks where prompts start
+    marks_input: bool,
+    /// Stream offset right after the output observed last
+    observed_offset: u64,
+    /// Output between the end of the prompt and the start of the command
+    input_echo: Option<Vec<u8>>,
+    next_command_id: u64,
+    scanner: osc::OscScanner,
+    /// When the user last typed, and the scrollback offset output had reached then
+    last_input: Option<(Instant, u64)>,
+    /// Last output that wasn't the echo of input
+    last_output: Option<Instant>,
+    pub activity: ActivityState,
+    /// Agent in the foreground when activity was last classified
+    pub agent: Option<&'static str>,
+    /// Lines output triggers asked to highlight, oldest first
+    markers: VecDeque<TriggerMatch>,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+impl TerminalState {
+    pub fn new(cwd: Option<String>) -> Self {
+        Self {
+            cwd,
+            cwd_source: CwdSource::Initial,
+            commands: VecDeque::new(),
+            running: None,
+            prompt_offset: None,
+            prompt_ready: None,
+            shell_integration: false,
+            marks_input: false,
+            observed_offset: 0,
+            input_echo: None,
+            next_command_id: 1,
+            scanner: osc::OscScanner::new(),
+            last_input: None,
+            last_output: None,
+            activity: ActivityState::Working,
+            agent: None,
+            markers: VecDeque::new(),
+        }
+    }
+
+    /// Record the current directory, returning an event if it changed.
+    /// Once the shell reports its directory, process lookups no longer override it.
+    pub fn set_cwd(&mut self, cwd: String, source: CwdSource) -> Option<TerminalEvent> {
+        if source == CwdSource::Process && self.cwd_source == CwdSource::Osc7 {
+            return None;
+        }
+        self.cwd_source = source;
+        if self.cwd.as_deref() == Some(cwd.as_str()) {
+            return None;
+        }
+        self.cwd = Some(cwd.clone());
+        Some(TerminalEvent::CwdChanged { cwd, source })
+    }
+
+    /// Finished commands, oldest first, followed by the running one if any.
+    pub fn command_history(&self) -> Vec<CommandRecord> {
+        let mut history: Vec<CommandRecord> = self.commands.iter().cloned().collect();
+        history.extend(self.running.as_ref().map(|(record, _)| record.clone()));
+        history
+    }
+
+    pub fn add_marker(&mut self, marker: TriggerMatch) {
+        if self.markers.len() == MAX_MARKERS {
+            self.markers.pop_front();
+        }
+        self.markers.push_back(marker);
+    }
+
+    pub fn markers(&self) -> Vec<TriggerMatch> {
+        self.markers.iter().cloned().collect()
+    }
+
+    /// The user sent input while output had reached scrollback offset `offset`.
+    pub fn record_input(&mut self, offset: u64) {
+        self.last_input = Some((Instant::now(), offset));
+    }
+
+    /// Scrollback offset of the output that followed the user's last input.
+    pub fn input_offset(&self) -> u64 {
+        self.last_input.map_or(0, |(_, offset)| offset)
+    }
+
+    /// Where the prompt the shell is waiting at ends, from its OSC 133 `B` marker.
+    /// `None` while a command runs, and for shells without integration.
+    pub fn prompt_ready_at(&self) -> Option<u64> {
+        self.prompt_ready
+    }
+
+    /// Where the prompt the shell is waiting at starts, from its OSC 133 `A` marker.
+    pub fn prompt_start_at(&self) -> Option<u64> {
+        self.prompt_offset
+    }
+
+    /// Whether the shell marks the end of its prompts, so `prompt_ready_at` can be relied on.
+    pub fn marks_input(&self) -> bool {
+        self.marks_input
+    }
+
+    /// Stream offset the reader has got to, which the scrollback may not have caught up with yet.
+    pub fn observed_offset(&self) -> u64 {
+        self.observed_offset
+    }
+
+    /// Time since the last output that wasn't echoed input.
+    pub fn quiet_for(&self) -> Option<Duration> {
+        self.last_output.map(|at| at.elapsed())
+    }
+
+    /// Record a new classification, returning an event if it changed.
+    pub fn set_activity(&mut self, state: ActivityState, agent: Option<&'static str>) -> Option<TerminalEvent> {
+        if state == self.activity && agent == self.agent {
+            return None;
+        }
+        let previous = std::mem::replace(&mut self.activity, state);
+        self.agent = agent;
+        Some(TerminalEvent::ActivityChanged { state, previous, agent })
+    }
+
+    /// Feed raw PTY output that starts at stream position `offset`.
+    pub fn observe(&mut self, bytes: &[u8], offset: u64) -> Vec<TerminalEvent> {
+        if self.last_input.is_none_or(|(at, _)| at.elapsed() >= ECHO_WINDOW) {
+            self.last_output = Some(Instant::now());
+        }
+
+        self.observed_offset = offset + bytes.len() as u64;
+
+        let mut events = Vec::new();
+        let mut pos = 0;
+        for seq in self.scanner.feed(bytes) {
+            self.record_echo(&bytes[pos..seq.end]);
+            pos = seq.end;
+            let at = offset + seq.end as u64;
+
+            match seq.code.as_str() {
+                "7" => events.extend(
+                    osc::parse_osc7_path(&seq.data).and_then(|cwd| self.set_cwd(cwd, CwdSource::Osc7)),
+                ),
+                "133" => self.handle_marker(&seq.data, at, &mut events),
+                _ => {}
+            }
+        }
+        self.record_echo(&bytes[pos..]);
+        if self.scanner.take_bell() {
+            events.push(TerminalEvent::Bell);
+        }
+        events
+    }
+
+    fn record_echo(&mut self, bytes: &[u8]) {
+        if let Some(echo) = &mut self.input_echo {
+            let room = MAX_INPUT_ECHO.saturating_sub(echo.len());
+            echo.extend_from_slice(&bytes[..bytes.len().min(room)]);
+        }
+    }
+
+    /// Handle `A` (prompt start), `B` (input start), `C` (command start) and `D` (command end).
+    fn handle_marker(&mut self, data: &str, at: u64, events: &mut Vec<TerminalEvent>) {
+        self.shell_integration = true;
+        let mut params = data.split(';');
+        match params.next().unwrap_or_default() {
+            "A" => {
+                // A new prompt without an end marker means the shell lost track of the command
+                events.extend(self.finish_command(None, at));
+                self.prompt_offset = Some(at);
+                self.prompt_ready = None;
+                self.input_echo = None;
+            }
+            "B" => {
+                self.marks_input = true;
+                self.prompt_ready = Some(at);
+                self.input_echo = Some(Vec::new());
+            }
+            "C" => {
+                self.prompt_ready = None;
+                let echo = self.input_echo.take();
+                let command = params
+                    .find_map(|p| p.strip_prefix("cmdline_url="))
+                    .map(osc::percent_decode)
+                    .or_else(|| echo.and_then(|echo| echoed_command(&echo)));
+                events.extend(self.finish_command(None, at));
+
+                let record = CommandRecord {
+                    id: self.next_command_id,
+                    command,
+                    cwd: self.cwd.clone(),
+                    started_at: now_millis(),
+                    duration_ms: None,
+                    exit_code: None,
+                    prompt_offset: self.prompt_offset.take(),
+                    output_offset: at,
+                    end_offset: None,
+                };
+                self.next_command_id += 1;
+                self.running = Some((record.clone(), Instant::now()));
+                events.push(TerminalEvent::CommandStarted(record));
+            }
+            "D" => {
+                let exit_code = params.next().and_then(|code| code.trim().parse().ok());
+                events.extend(self.finish_command(exit_code, at));
+            }
+            _ => {}
+        }
+    }
+
+    fn finish_command(&mut self, exit_code: Option<i32>, at: u64) -> Option<TerminalEvent> {
+        let (mut record, started) = self.running.take()?;
+        record.duration_ms = Some(started.elapsed().as_millis() as u64);
+        record.exit_code = exit_code;
+        record.end_offset = Some(at);
+
+        if self.commands.len() == MAX_COMMAND_HISTORY {
+            self.commands.pop_front();
+        }
+        self.commands.push_back(record.clone());
+        Some(TerminalEvent::CommandFinished(record))
+    }
+}
+
+/// The command line as echoed after the prompt: the last line of plain text.
+fn echoed_command(echo: &[u8]) -> Option<String> {
+    let text = ansi::strip_ansi(echo);
+    let line = text.lines().rev().find(|l| !l.trim().is_empty())?.trim();
+    Some(line.to_string())
+}
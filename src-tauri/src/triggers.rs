@@ -0,0 +1,311 @@
+//! User-defined rules that watch PTY output line by line, e.g. `FAILED|panicked at`
+//! in every session under `PROJ-*`, and report matches as events, notifications or markers.
+
+use crate::ansi;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, sync::RwLock};
+
+/// Lines longer than this are matched in pieces.
+const MAX_LINE_BYTES: usize = 16 * 1024;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TriggerRule {
+    pub name: String,
+    /// Regular expression matched against each line of output, without escape sequences
+    pub pattern: String,
+    /// Issue keys the rule applies to, with `*` as a wildcard, e.g. "PROJ-*". Every session if unset
+    pub issues: Option<String>,
+    pub enabled: bool,
+    /// Show a desktop notification on a match
+    pub notify: bool,
+    /// Keep a marker for the frontend to highlight
+    pub mark: bool,
+}
+
+impl Default for TriggerRule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            pattern: String::new(),
+            issues: None,
+            enabled: true,
+            notify: false,
+            mark: true,
+        }
+    }
+}
+
+/// A line that matched a rule. Offsets span the match in the session's output stream,
+/// escape sequences within it included.
+#[derive(Clone, Debug, Serialize)]
+pub struct TriggerMatch {
+    pub rule: String,
+    pub text: String,
+    pub start_offset: u64,
+    pub end_offset: u64,
+    #[serde(skip)]
+    pub notify: bool,
+    #[serde(skip)]
+    pub mark: bool,
+}
+
+/// One line of output as plain text.
+pub struct OutputLine {
+    pub text: String,
+    pub start_offset: u64,
+    pub end_offset: u64,
+    /// Index in the raw line of each byte of `text`
+    sources: Vec<usize>,
+}
+
+impl OutputLine {
+    /// Stream offsets of a byte range of `text`.
+    fn stream_range(&self, range: std::ops::Range<usize>) -> (u64, u64) {
+        let raw_len = self.end_offset.saturating_sub(self.start_offset) as usize;
+        let start = self.sources.get(range.start).copied().unwrap_or(raw_len);
+        let end = if range.is_empty() {
+            start
+        } else {
+            self.sources.get(range.end - 1).map_or(raw_len, |&i| i + 1)
+        };
+        (self.start_offset + start as u64, self.start_offset + end as u64)
+    }
+}
+
+/// Cuts a session's output into lines. Escape sequences are stripped once a line is complete,
+/// so ones split across reads don't leak into the text.
+#[derive(Default)]
+pub struct LineSplitter {
+    line: Vec<u8>,
+    start_offset: u64,
+}
+
+impl LineSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed raw output that starts at stream position `offset`; returns the lines it completed.
+    pub fn feed(&mut self, bytes: &[u8], offset: u64) -> Vec<OutputLine> {
+        let mut lines = Vec::new();
+        for (i, &b) in bytes.iter().enumerate() {
+            let at = offset + i as u64;
+            if self.line.is_empty() {
+                self.start_offset = at;
+            }
+            if b != b'\n' {
+                self.line.push(b);
+            }
+            if b == b'\n' || self.line.len() >= MAX_LINE_BYTES {
+                lines.extend(self.take(at + 1));
+            }
+        }
+        lines
+    }
+
+    /// The unfinished last line, if any.
+    pub fn finish(&mut self, end_offset: u64) -> Option<OutputLine> {
+        self.take(end_offset)
+    }
+
+    fn take(&mut self, end_offset: u64) -> Option<OutputLine> {
+        let (mut text, sources) = ansi::strip_ansi_mapped(&std::mem::take(&mut self.line));
+        text.truncate(text.trim_end().len());
+        (!text.trim().is_empty()).then_some(OutputLine {
+            text,
+            start_offset: self.start_offset,
+            end_offset,
+            sources,
+        })
+    }
+}
+
+struct CompiledTrigger {
+    rule: TriggerRule,
+    pattern: Regex,
+    issues: Option<Regex>,
+}
+
+impl CompiledTrigger {
+    fn new(rule: TriggerRule) -> Result<Self, String> {
+        let pattern = Regex::new(&rule.pattern).map_err(|e| format!("Invalid pattern: {}", e))?;
+        let issues = rule
+            .issues
+            .as_deref()
+            .filter(|glob| !glob.trim().is_empty())
+            .map(|glob| {
+                let escaped = regex::escape(glob.trim()).replace(r"\*", ".*");
+                Regex::new(&format!("^{}$", escaped)).map_err(|e| format!("Invalid issue pattern: {}", e))
+            })
+            .transpose()?;
+        Ok(Self { rule, pattern, issues })
+    }
+
+    fn applies_to(&self, issue_key: Option<&str>) -> bool {
+        match (&self.issues, issue_key) {
+            (None, _) => true,
+            (Some(issues), Some(key)) => issues.is_match(key),
+            (Some(_), None) => false,
+        }
+    }
+
+    fn check(&self, line: &OutputLine) -> Option<TriggerMatch> {
+        let found = self.pattern.find(&line.text)?;
+        let (start_offset, end_offset) = line.stream_range(found.range());
+        Some(TriggerMatch {
+            rule: self.rule.name.clone(),
+            text: line.text.clone(),
+            start_offset,
+            end_offset,
+            notify: self.rule.notify,
+            mark: self.rule.mark,
+        })
+    }
+}
+
+/// The saved rules, compiled. Shared by every session's reader thread.
+pub struct Triggers {
+    compiled: RwLock<Vec<CompiledTrigger>>,
+}
+
+impl Triggers {
+    /// Start with the saved rules. Rules that fail to load or compile are left out.
+    pub fn load() -> Self {
+        let triggers = Self {
+            compiled: RwLock::new(Vec::new()),
+        };
+        let _ = triggers.reload();
+        triggers
+    }
+
+    /// Compile the saved rules again, after they changed.
+    pub fn reload(&self) -> Result<(), String> {
+        let compiled = load_rules()?
+            .into_iter()
+            .filter(|rule| rule.enabled)
+            .filter_map(|rule| CompiledTrigger::new(rule).ok())
+            .collect();
+        *self.compiled.write().unwrap() = compiled;
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.compiled.read().unwrap().is_empty()
+    }
+
+    /// Rules that match a line from a session opened for `issue_key`.
+    pub fn matches(&self, issue_key: Option<&str>, line: &OutputLine) -> Vec<TriggerMatch> {
+        self.compiled
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|trigger| trigger.applies_to(issue_key))
+            .filter_map(|trigger| trigger.check(line))
+            .collect()
+    }
+}
+
+/// Run a rule over saved output that starts at stream position `offset`,
+/// whatever its issue filter, to see what it would have matched.
+pub fn test_rule(rule: TriggerRule, output: &[u8], offset: u64) -> Result<Vec<TriggerMatch>, String> {
+    let trigger = CompiledTrigger::new(rule)?;
+    let mut splitter = LineSplitter::new();
+    let mut lines = splitter.feed(output, offset);
+    lines.extend(splitter.finish(offset + output.len() as u64));
+    Ok(lines.iter().filter_map(|line| trigger.check(line)).collect())
+}
+
+fn rules_path() -> Option<PathBuf> {
+    crate::app_data_dir().map(|p| p.join("output-triggers.json"))
+}
+
+pub fn load_rules() -> Result<Vec<TriggerRule>, String> {
+    let path = rules_path().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    match std::fs::read_to_string(&path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Failed to parse output triggers: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read output triggers: {}", e)),
+    }
+}
+
+pub fn save_rules(rules: &[TriggerRule]) -> Result<(), String> {
+    // Refuse rules that would be skipped silently when loaded
+    for rule in rules {
+        CompiledTrigger::new(rule.clone()).map_err(|e| format!("{}: {}", rule.name, e))?;
+    }
+    let path = rules_path().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    let content = serde_json::to_string_pretty(rules).map_err(|e| format!("Failed to serialize output triggers: {}", e))?;
+    std::fs::write(&path, content).map_err(|e| format!("Failed to write output triggers: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str) -> TriggerRule {
+        TriggerRule {
+            name: "test".to_string(),
+            pattern: pattern.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn splits_lines_across_reads() {
+        let mut splitter = LineSplitter::new();
+        assert!(splitter.feed(b"one\r", 100).is_empty());
+        let lines = splitter.feed(b"\ntwo\n\nthr", 104);
+        let texts: Vec<_> = lines.iter().map(|line| line.text.as_str()).collect();
+        assert_eq!(texts, ["one", "two"]);
+        assert_eq!((lines[0].start_offset, lines[0].end_offset), (100, 105));
+        assert_eq!((lines[1].start_offset, lines[1].end_offset), (105, 109));
+
+        let last = splitter.finish(113).unwrap();
+        assert_eq!((last.text.as_str(), last.start_offset), ("thr", 110));
+    }
+
+    #[test]
+    fn strips_sequences_split_across_reads() {
+        let mut splitter = LineSplitter::new();
+        splitter.feed(b"\x1b[3", 0);
+        let lines = splitter.feed(b"1mred\x1b[0m\n", 4);
+        assert_eq!(lines[0].text, "red");
+    }
+
+    #[test]
+    fn marks_the_match_not_the_line() {
+        let output = b"ok\ntest foo ... FAILED\n";
+        let matches = test_rule(rule("FAILED"), output, 1000).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].text, "test foo ... FAILED");
+        let (start, end) = (matches[0].start_offset, matches[0].end_offset);
+        assert_eq!(&output[(start - 1000) as usize..(end - 1000) as usize], b"FAILED");
+    }
+
+    #[test]
+    fn match_offsets_skip_escape_sequences() {
+        // Colors before and inside the match shift it in the raw output
+        let output = "\x1b[1mtest\x1b[0m \x1b[31mpan\x1b[1micked\x1b[0m at é\n".as_bytes();
+        let matches = test_rule(rule("panicked at é"), output, 0).unwrap();
+        let (start, end) = (matches[0].start_offset as usize, matches[0].end_offset as usize);
+        assert_eq!(&output[start..end], "pan\x1b[1micked\x1b[0m at é".as_bytes());
+    }
+
+    #[test]
+    fn issue_filter_uses_wildcards() {
+        let trigger = CompiledTrigger::new(TriggerRule {
+            issues: Some("PROJ-*".to_string()),
+            ..rule("x")
+        })
+        .unwrap();
+        assert!(trigger.applies_to(Some("PROJ-12")));
+        assert!(!trigger.applies_to(Some("OTHER-1")));
+        assert!(!trigger.applies_to(None));
+        assert!(CompiledTrigger::new(rule("(")).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Incremental escape-sequence tracking for the reader thread, so PTY output is only
+//! ever cut between whole sequences and whole UTF-8 characters, however long they get.
+//!
+//! Follows the VT500 parser states closely enough to find sequence ends: CSI, OSC,
+//! DCS, SOS, PM, APC, ESC with intermediates, CAN/SUB aborts, and the C1 introducers
+//! (U+0090, U+0098, U+009B, U+009D-U+009F) as the UTF-8 the terminal view decodes.
+//! Everything else that picks things out of output, like plain text, OSCs and clipboard
+//! requests, walks it with [`VtState`], so they all agree on where sequences end.
+//! Uses std only, so the fuzz target under `fuzz/` can include it directly.
+
+/// Sequences are held back until complete up to this size. 4 MiB fits OSC 52
+/// clipboard payloads and inline images.
+pub const DEFAULT_MAX_SEQUENCE_BYTES: usize = 4 * 1024 * 1024;
+
+const BEL: u8 = 0x07;
+const ESC: u8 = 0x1b;
+const CAN: u8 = 0x18;
+const SUB: u8 = 0x1a;
+/// Lead byte of U+0080-U+00BF, the range holding the C1 controls
+const C1_LEAD: u8 = 0xc2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Ground,
+    /// Inside a UTF-8 character, waiting for `remaining` continuation bytes
+    Utf8 { lead: u8, remaining: u8 },
+    Escape,
+    /// ESC followed by intermediates such as `(`, waiting for the final byte
+    EscapeIntermediate,
+    Csi,
+    /// OSC (ended by BEL or ST), or DCS, SOS, PM and APC (ended by ST only)
+    String { osc: bool },
+    /// 0xC2 inside a string, which may start a UTF-8 encoded ST
+    StringC1 { osc: bool },
+}
+
+fn step(state: State, b: u8) -> State {
+    match (state, b) {
+        // CAN and SUB abort any sequence
+        (_, CAN | SUB) => State::Ground,
+
+        (State::Ground, ESC) => State::Escape,
+        (State::Ground, 0xc2..=0xdf) => State::Utf8 { lead: b, remaining: 1 },
+        (State::Ground, 0xe0..=0xef) => State::Utf8 { lead: b, remaining: 2 },
+        (State::Ground, 0xf0..=0xf4) => State::Utf8 { lead: b, remaining: 3 },
+        // Text, C0 controls, and invalid bytes the view will replace
+        (State::Ground, _) => State::Ground,
+
+        (State::Utf8 { lead, remaining: 1 }, 0x80..=0xbf) => match (lead, b) {
+            (C1_LEAD, 0x9b) => State::Csi,
+            (C1_LEAD, 0x9d) => State::String { osc: true },
+            (C1_LEAD, 0x90 | 0x98 | 0x9e | 0x9f) => State::String { osc: false },
+            _ => State::Ground,
+        },
+        (State::Utf8 { lead, remaining }, 0x80..=0xbf) => State::Utf8 { lead, remaining: remaining - 1 },
+        // A malformed character ends early; the byte starts something new
+        (State::Utf8 { .. }, _) => step(State::Ground, b),
+
+        (State::Escape, b'[') => State::Csi,
+        (State::Escape, b']') => State::String { osc: true },
+        (State::Escape, b'P' | b'X' | b'^' | b'_') => State::String { osc: false },
+        (State::Escape, 0x20..=0x2f) => State::EscapeIntermediate,
+        (State::Escape, ESC) => State::Escape,
+        // C0 controls inside an escape are executed without ending it
+        (State::Escape, 0x00..=0x1f) => State::Escape,
+        (State::Escape, _) => State::Ground,
+
+        (State::EscapeIntermediate, 0x00..=0x1a | 0x1c..=0x2f) => State::EscapeIntermediate,
+        (State::EscapeIntermediate, ESC) => State::Escape,
+        (State::EscapeIntermediate, _) => State::Ground,
+
+        (State::Csi, 0x40..=0x7e) => State::Ground,
+        (State::Csi, ESC) => State::Escape,
+        (State::Csi, _) => State::Csi,
+
+        (State::String { osc: true }, BEL) => State::Ground,
+        // ESC ends the string: `ESC \` is ST, anything else starts a new sequence
+        (State::String { .. }, ESC) => State::Escape,
+        (State::String { osc }, C1_LEAD) => State::StringC1 { osc },
+        (State::String { osc }, _) => State::String { osc },
+
+        (State::StringC1 { .. }, 0x9c) => State::Ground,
+        (State::StringC1 { osc }, _) => step(State::String { osc }, b),
+    }
+}
+
+/// The parser state after some output, for walking it a byte at a time: to find where
+/// sequences and characters end in output cut up elsewhere, e.g. by the scrollback
+/// dropping its oldest bytes, or to tell text from sequences.
+#[derive(Clone, Copy, Debug)]
+pub struct VtState(State);
+
+impl Default for VtState {
+    fn default() -> Self {
+        Self(State::Ground)
+    }
+}
+
+impl VtState {
+    pub fn advance(&mut self, b: u8) {
+        self.0 = step(self.0, b);
+    }
+
+    /// Outside any escape sequence or character.
+    pub fn is_ground(&self) -> bool {
+        self.0 == State::Ground
+    }
+
+    /// Inside a UTF-8 character.
+    pub fn in_char(&self) -> bool {
+        matches!(self.0, State::Utf8 { .. })
+    }
+
+    /// Just after an ESC, before what it introduces is known.
+    pub fn in_escape(&self) -> bool {
+        self.0 == State::Escape
+    }
+
+    /// Inside an OSC, up to its terminator.
+    pub fn in_osc(&self) -> bool {
+        matches!(self.0, State::String { osc: true } | State::StringC1 { osc: true })
+    }
+
+    /// Inside a DCS, SOS, PM or APC string, which only ST ends.
+    pub fn in_string(&self) -> bool {
+        matches!(self.0, State::String { osc: false } | State::StringC1 { osc: false })
+    }
+}
+
+/// Holds back the unfinished tail of PTY output until the sequence or character
+/// it ends in is complete.
+pub struct VtSplitter {
+    state: State,
+    held: Vec<u8>,
+    max_sequence_bytes: usize,
+}
+
+impl VtSplitter {
+    pub fn new(max_sequence_bytes: usize) -> Self {
+        Self {
+            state: State::Ground,
+            held: Vec::new(),
+            max_sequence_bytes,
+        }
+    }
+
+    pub fn set_max_sequence_bytes(&mut self, max_sequence_bytes: usize) {
+        self.max_sequence_bytes = max_sequence_bytes;
+    }
+
+    /// Feed output; returns everything that is safe to pass on, possibly nothing.
+    pub fn split(&mut self, bytes: &[u8]) -> Vec<u8> {
+        let start = self.held.len();
+        self.held.extend_from_slice(bytes);
+
+        let mut boundary = None;
+        for (i, &b) in bytes.iter().enumerate() {
+            self.state = step(self.state, b);
+            if self.state == State::Ground {
+                boundary = Some(start + i + 1);
+            }
+        }
+
+        let mut ready = match boundary {
+            Some(end) => {
+                let rest = self.held.split_off(end);
+                std::mem::replace(&mut self.held, rest)
+            }
+            None => Vec::new(),
+        };
+
+        // Nothing legitimate is this long; let it through rather than buffer forever
+        if self.held.len() > self.max_sequence_bytes {
+            ready.append(&mut self.held);
+            self.state = State::Ground;
+        }
+        ready
+    }
+
+    /// Whatever is still held back, at the end of the output.
+    pub fn finish(&mut self) -> Vec<u8> {
+        self.state = State::Ground;
+        std::mem::take(&mut self.held)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `reads` one at a time, collecting the chunks let out.
+    fn split_all(splitter: &mut VtSplitter, reads: &[&[u8]]) -> Vec<Vec<u8>> {
+        let mut chunks: Vec<Vec<u8>> = reads.iter().map(|read| splitter.split(read)).collect();
+        chunks.push(splitter.finish());
+        chunks
+    }
+
+    #[test]
+    fn holds_back_unfinished_sequences() {
+        let mut splitter = VtSplitter::new(DEFAULT_MAX_SEQUENCE_BYTES);
+        let chunks = split_all(&mut splitter, &[b"ab\x1b[3", b"1mc\x1b]8;;http://x", b"\x1b\\d\x1b(", b"B"]);
+        assert_eq!(
+            chunks,
+            [b"ab".to_vec(), b"\x1b[31mc".to_vec(), b"\x1b]8;;http://x\x1b\\d".to_vec(), b"\x1b(B".to_vec(), Vec::new()]
+        );
+    }
+
+    #[test]
+    fn holds_back_unfinished_characters() {
+        let mut splitter = VtSplitter::new(DEFAULT_MAX_SEQUENCE_BYTES);
+        let bytes = "a한".as_bytes();
+        assert_eq!(splitter.split(&bytes[..2]), b"a");
+        assert_eq!(splitter.split(&bytes[2..3]), b"");
+        assert_eq!(splitter.split(&bytes[3..]), "한".as_bytes());
+    }
+
+    #[test]
+    fn bel_ends_only_osc() {
+        let mut splitter = VtSplitter::new(DEFAULT_MAX_SEQUENCE_BYTES);
+        assert_eq!(splitter.split(b"\x1b]0;t\x07"), b"\x1b]0;t\x07");
+        // A BEL inside DCS, SOS, PM or APC is part of the string
+        for intro in [b'P', b'X', b'^', b'_'] {
+            assert_eq!(splitter.split(&[0x1b, intro, b'x', 0x07]), b"");
+            assert_eq!(splitter.split(b"\x1b\\"), [0x1b, intro, b'x', 0x07, 0x1b, b'\\']);
+        }
+    }
+
+    #[test]
+    fn handles_c1_introducers_and_terminators() {
+        let mut splitter = VtSplitter::new(DEFAULT_MAX_SEQUENCE_BYTES);
+        // U+009D starts an OSC and U+009C ends it, both as UTF-8
+        assert_eq!(splitter.split(b"\xc2\x9d52;c;aGk="), b"");
+        assert_eq!(splitter.split(b"\xc2"), b"");
+        assert_eq!(splitter.split(b"\x9cz"), b"\xc2\x9d52;c;aGk=\xc2\x9cz");
+        // U+009B starts a CSI
+        assert_eq!(splitter.split(b"\xc2\x9b1"), b"");
+        assert_eq!(splitter.split(b"m"), b"\xc2\x9b1m");
+        // Other characters in the C2 range are just text
+        assert_eq!(splitter.split("é".as_bytes()), "é".as_bytes());
+    }
+
+    #[test]
+    fn can_and_sub_abort_sequences() {
+        let mut splitter = VtSplitter::new(DEFAULT_MAX_SEQUENCE_BYTES);
+        assert_eq!(splitter.split(b"\x1b]0;title\x18x"), b"\x1b]0;title\x18x");
+        assert_eq!(splitter.split(b"\x1bPq\x1a"), b"\x1bPq\x1a");
+    }
+
+    #[test]
+    fn lets_out_sequences_over_the_cap() {
+        let mut splitter = VtSplitter::new(8);
+        assert_eq!(splitter.split(b"\x1b]0;abc"), b"");
+        assert_eq!(splitter.split(b"def"), b"\x1b]0;abcdef");
+        // Back in the ground state afterwards
+        assert_eq!(splitter.split(b"g"), b"g");
+    }
+
+    #[test]
+    fn state_tracks_boundaries() {
+        let mut state = VtState::default();
+        for &b in b"\x1b[1" {
+            state.advance(b);
+        }
+        assert!(!state.is_ground());
+        state.advance(b'm');
+        assert!(state.is_ground());
+    }
+}
@@ -1,9 +1,9 @@
 import React, { useState, useCallback, useEffect, useRef, Fragment, useMemo, createContext, useContext } from "react";
 import { fetch } from "@tauri-apps/plugin-http";
 import { openUrl } from "@tauri-apps/plugin-opener";
-import { invoke } from "@tauri-apps/api/core";
-import { listen } from "@tauri-apps/api/event";
+import { invoke, Channel } from "@tauri-apps/api/core";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import { listen } from "@tauri-apps/api/event";
 import { open as openDialog } from "@tauri-apps/plugin-dialog";
 import Markdown from "react-markdown";
 import remarkGfm from "remark-gfm";
@@ -3213,6 +3213,27 @@ function setIssueTerminalState(issueKey: string, state: Partial<IssueTerminalSta
   issueTerminalStates.set(issueKey, { ...current, ...state });
 }
 
+// Create a PTY and tag it with its issue, so notifications can be muted per issue and lead back to it
+async function createPtySession(issueKey: string, cwd: string): Promise<number> {
+  const sessionId = await invoke<number>("create_pty_session", { rows: 24, cols: 80, cwd });
+  invoke("set_pty_session_issue", { sessionId, issueKey }).catch(console.error);
+  return sessionId;
+}
+
+// Make a session the active tab of its group, and its group the active one
+function focusIssueTerminal(issueKey: string, sessionId: number) {
+  const state = getIssueTerminalState(issueKey);
+  const group = state.groups.find(g => g.terminals.includes(sessionId));
+  if (!group) return;
+  setIssueTerminalState(issueKey, {
+    groups: state.groups.map(g => g.id === group.id ? { ...g, activeTerminal: sessionId } : g),
+    activeGroupId: group.id,
+  });
+}
+
+// Sent when the window regains focus right after a terminal notification, i.e. it was clicked
+type NotificationTarget = { session_id: number; issue_key: string | null };
+
 const MaximizeIcon = () => (
   <svg className="icon-xs" viewBox="0 0 24 24" fill="none" stroke="currentColor" strokeWidth="2">
     <rect x="3" y="3" width="18" height="18" rx="2" />
@@ -3242,92 +3263,31 @@ const TrashIcon = () => (
   </svg>
 );
 
+// How a PTY's child process ended (sent with the end message)
+interface PtyExit {
+  code: number;
+  signal: string | null;
+  success: boolean;
+  runtime_ms: number;
+}
+
 // Global cache for terminal instances - persists across component remounts
 // Simple terminal cache
 const terminalCache = new Map<number, {
   term: Terminal;
   fitAddon: FitAddon;
   cleanup?: () => void;
-  onSessionEnd?: () => void;
+  onSessionEnd?: (exit: PtyExit | null) => void;
   onTitleChange?: (title: string) => void;
 }>();
 
 // Global terminal titles (sessionId -> title)
 const terminalTitles = new Map<number, string>();
 
-// Find safe boundary to write, excluding incomplete escape sequences at the end
-function findSafeWriteBoundary(text: string): number {
-  const len = text.length;
-  if (len === 0) return 0;
-
-  // Look for ESC (0x1b) in the last 64 chars
-  const searchStart = Math.max(0, len - 64);
-  let lastEsc = -1;
-
-  for (let i = len - 1; i >= searchStart; i--) {
-    if (text.charCodeAt(i) === 0x1b) {
-      lastEsc = i;
-      break;
-    }
-  }
-
-  if (lastEsc === -1) return len; // No ESC found
-
-  // Check if sequence starting at lastEsc is complete
-  const seq = text.slice(lastEsc);
-
-  if (isEscapeSequenceComplete(seq)) {
-    return len; // Complete, safe to write all
-  }
-
-  return lastEsc; // Incomplete, write up to ESC
-}
-
-function isEscapeSequenceComplete(seq: string): boolean {
-  if (seq.length === 0 || seq.charCodeAt(0) !== 0x1b) return true;
-  if (seq.length === 1) return false; // Just ESC
-
-  const second = seq.charCodeAt(1);
-
-  // CSI: ESC [ ... (ends with 0x40-0x7E)
-  if (second === 0x5b) { // '['
-    if (seq.length === 2) return false;
-    for (let i = 2; i < seq.length; i++) {
-      const c = seq.charCodeAt(i);
-      if (c >= 0x40 && c <= 0x7e) return true;
-    }
-    return false;
-  }
-
-  // OSC: ESC ] ... (ends with BEL or ST)
-  if (second === 0x5d) { // ']'
-    for (let i = 2; i < seq.length; i++) {
-      if (seq.charCodeAt(i) === 0x07) return true; // BEL
-      if (seq.charCodeAt(i) === 0x1b && i + 1 < seq.length && seq.charCodeAt(i + 1) === 0x5c) {
-        return true; // ST (ESC \)
-      }
-    }
-    return false;
-  }
-
-  // DCS: ESC P ... (ends with ST)
-  if (second === 0x50) { // 'P'
-    for (let i = 2; i < seq.length; i++) {
-      if (seq.charCodeAt(i) === 0x1b && i + 1 < seq.length && seq.charCodeAt(i + 1) === 0x5c) {
-        return true;
-      }
-    }
-    return false;
-  }
-
-  // Single char sequences - complete
-  return true;
-}
-
 function TerminalInstance({ sessionId, fontSize, onSessionEnd, onTitleChange }: {
   sessionId: number;
   fontSize: number;
-  onSessionEnd?: () => void;
+  onSessionEnd?: (exit: PtyExit | null) => void;
   onTitleChange?: (title: string) => void;
 }) {
   const containerRef = useRef<HTMLDivElement>(null);
@@ -3532,10 +3492,6 @@ function TerminalInstance({ sessionId, fontSize, onSessionEnd, onTitleChange }:
       invoke("write_to_pty", { sessionId, data }).catch(console.error);
     });
 
-    // Event-based output handling
-    let unlistenOutput: (() => void) | null = null;
-    let unlistenEnd: (() => void) | null = null;
-
     // Foreground process polling (250ms)
     // Debounce to avoid flickering from short-lived processes
     let lastTitle = "";
@@ -3545,7 +3501,7 @@ function TerminalInstance({ sessionId, fontSize, onSessionEnd, onTitleChange }:
     const pollForegroundProcess = async () => {
       while (polling) {
         try {
-          const name = await invoke<string>("get_pty_foreground_process", { sessionId });
+          const { name } = await invoke<{ name: string }>("get_pty_foreground_process", { sessionId });
           if (name && name !== lastTitle) {
             // Require same value twice in a row to update (filters out short-lived processes)
             if (name === pendingTitle) {
@@ -3568,48 +3524,46 @@ function TerminalInstance({ sessionId, fontSize, onSessionEnd, onTitleChange }:
     };
     pollForegroundProcess();
 
-    const setupListeners = async () => {
-      // Buffer for incomplete escape sequences
-      let pendingData = '';
-
-      unlistenOutput = await listen<string>(`pty-output-${sessionId}`, (event) => {
-        const data = pendingData + event.payload;
-        const safeEnd = findSafeWriteBoundary(data);
-
-        if (safeEnd > 0) {
-          term.write(data.slice(0, safeEnd));
-        }
-        pendingData = data.slice(safeEnd);
-
-        // Prevent memory buildup - force flush if too large
-        if (pendingData.length > 256) {
-          term.write(pendingData);
-          pendingData = '';
+    // Output arrives on a per-session channel: scrollback first, then live bytes in order.
+    // Each message starts with a tag byte (0 = output, 1 = session ended).
+    const outputChannel = new Channel<ArrayBuffer>();
+    let ended = false;
+    outputChannel.onmessage = (message) => {
+      const bytes = new Uint8Array(message);
+      if (bytes[0] === 0) {
+        const data = bytes.subarray(1);
+        // Ack once rendered so the backend can hold output back while we're behind
+        term.write(data, () => {
+          invoke("ack_pty_output", { sessionId, bytes: data.length }).catch(() => {});
+        });
+      } else if (bytes[0] === 1 && !ended) {
+        ended = true;
+        let exit: PtyExit | null = null;
+        try {
+          exit = JSON.parse(new TextDecoder().decode(bytes.subarray(1)));
+        } catch {
+          // No exit status
         }
-      });
-
-      unlistenEnd = await listen(`pty-end-${sessionId}`, () => {
         const c = terminalCache.get(sessionId);
         if (c) {
           c.cleanup?.();
-          c.onSessionEnd?.();
+          c.onSessionEnd?.(exit);
         }
-      });
-
-      // Store cleanup function
-      const cached = terminalCache.get(sessionId);
-      if (cached) {
-        cached.cleanup = () => {
-          unlistenOutput?.();
-          unlistenEnd?.();
-        };
       }
     };
 
-    setupListeners();
+    invoke("attach_pty_session", { sessionId, onEvent: outputChannel }).catch(console.error);
 
     // Cache it
-    terminalCache.set(sessionId, { term, fitAddon, onSessionEnd, onTitleChange });
+    terminalCache.set(sessionId, {
+      term,
+      fitAddon,
+      cleanup: () => {
+        outputChannel.onmessage = () => {};
+      },
+      onSessionEnd,
+      onTitleChange,
+    });
 
     return () => {};
   }, [sessionId]);
@@ -3994,7 +3948,7 @@ function TerminalPanel({ issueKey, projectKey, isCollapsed, setIsCollapsed, isMa
         const info = { path: worktreePath, branch: targetBranch, baseBranch: branchMode === "new" ? baseBranch : defaultBaseBranch, repoPath };
         saveIssueWorktree(projectKey, capturedIssueKey, info);
 
-        const sessionId = await invoke("create_pty_session", { rows: 24, cols: 80, cwd: worktreePath }) as number;
+        const sessionId = await createPtySession(capturedIssueKey, worktreePath);
 
         // Check if this request is still valid after async operation
         if (createRequestIds.get(capturedIssueKey) !== requestId) return;
@@ -4051,7 +4005,7 @@ function TerminalPanel({ issueKey, projectKey, isCollapsed, setIsCollapsed, isMa
         saveIssueWorktree(projectKey, capturedIssueKey, info);
 
         // Create terminal and run setup.sh immediately (even if on different issue)
-        const sessionId = await invoke("create_pty_session", { rows: 24, cols: 80, cwd: worktreePath }) as number;
+        const sessionId = await createPtySession(capturedIssueKey, worktreePath);
         const newGroup = { id: 1, terminals: [sessionId], activeTerminal: sessionId, flex: 1 };
 
         setIssueTerminalState(capturedIssueKey, {
@@ -4088,7 +4042,7 @@ function TerminalPanel({ issueKey, projectKey, isCollapsed, setIsCollapsed, isMa
         saveIssueWorktree(projectKey, capturedIssueKey, info);
 
         // Create terminal and run setup.sh immediately (even if on different issue)
-        const sessionId = await invoke("create_pty_session", { rows: 24, cols: 80, cwd: worktreePath }) as number;
+        const sessionId = await createPtySession(capturedIssueKey, worktreePath);
         const newGroup = { id: 1, terminals: [sessionId], activeTerminal: sessionId, flex: 1 };
 
         setIssueTerminalState(capturedIssueKey, {
@@ -4259,6 +4213,26 @@ function TerminalPanel({ issueKey, projectKey, isCollapsed, setIsCollapsed, isMa
     setProjectRepoPaths(getProjectRepoPaths());
   }, [issueKey, projectKey]);
 
+  // The terminal on screen doesn't send notifications while the window has focus
+  const focusedSessionId = groups.find(g => g.id === activeGroupId)?.activeTerminal ?? null;
+  useEffect(() => {
+    invoke("set_focused_pty_session", { sessionId: focusedSessionId }).catch(console.error);
+  }, [focusedSessionId]);
+
+  // Switch to the tab a clicked notification came from
+  useEffect(() => {
+    const unlisten = listen<NotificationTarget>("notification-activated", ({ payload }) => {
+      if (payload.issue_key !== issueKeyRef.current) return;
+      focusIssueTerminal(payload.issue_key, payload.session_id);
+      const state = getIssueTerminalState(payload.issue_key);
+      setGroupsState(state.groups);
+      setActiveGroupIdState(state.activeGroupId);
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, []);
+
   // Check for open PR when worktree is set
   useEffect(() => {
     if (!worktreeInfo || !repoPath) {
@@ -4341,8 +4315,20 @@ function TerminalPanel({ issueKey, projectKey, isCollapsed, setIsCollapsed, isMa
     // Create PTY and handle setup.sh
     (async () => {
       try {
-        const sessionId = await invoke("create_pty_session", { rows: 24, cols: 80, cwd: currentTerminalPath }) as number;
-        const newGroup = { id: groupId, terminals: [sessionId], activeTerminal: sessionId, flex: 1 };
+        // Reattach sessions still running for this issue before starting new shells: ones the
+        // PTY host kept since the last launch, and ones left behind when the webview reloaded
+        const sessions = await invoke<{ session_id: number; cwd: string | null; attached: boolean; exited: boolean; issue_key: string | null }[]>("list_pty_sessions").catch(() => []);
+        const resumable = sessions
+          .filter(s => !s.attached && !s.exited)
+          .filter(s => s.issue_key ? s.issue_key === capturedIssueKey : s.cwd === currentTerminalPath)
+          .map(s => s.session_id);
+        const terminals = resumable.length > 0
+          ? resumable
+          : [await createPtySession(capturedIssueKey, currentTerminalPath)];
+        for (const sessionId of resumable) {
+          invoke("set_pty_session_issue", { sessionId, issueKey: capturedIssueKey }).catch(console.error);
+        }
+        const newGroup = { id: groupId, terminals, activeTerminal: terminals[terminals.length - 1], flex: 1 };
 
         // Save to global state for the captured issue
         setIssueTerminalState(capturedIssueKey, {
@@ -4385,7 +4371,7 @@ function TerminalPanel({ issueKey, projectKey, isCollapsed, setIsCollapsed, isMa
     }
 
     try {
-      const sessionId: number = await invoke("create_pty_session", { rows: 24, cols: 80, cwd });
+      const sessionId = await createPtySession(capturedIssueKey, cwd);
       const newGroup = { id: groupId, terminals: [sessionId], activeTerminal: sessionId, flex: 1 };
 
       // Always save to global state for the captured issue
@@ -4409,10 +4395,21 @@ function TerminalPanel({ issueKey, projectKey, isCollapsed, setIsCollapsed, isMa
     const capturedIssueKey = issueKey;
 
     const currentWorktree = getIssueWorktree(projectKey, capturedIssueKey);
-    const cwd = currentWorktree?.path;
-    if (!cwd) return;
+    const worktreePath = currentWorktree?.path;
+    if (!worktreePath) return;
+    // Open the split where the group's active terminal currently is, if that's inside the worktree
+    let cwd = worktreePath;
+    const activeTerminal = getIssueTerminalState(capturedIssueKey).groups.find(g => g.id === targetGroupId)?.activeTerminal;
+    if (activeTerminal != null) {
+      try {
+        const info = await invoke<{ cwd: string | null }>("get_pty_session_info", { sessionId: activeTerminal });
+        if (info.cwd && (info.cwd === worktreePath || info.cwd.startsWith(`${worktreePath}/`))) {
+          cwd = info.cwd;
+        }
+      } catch {}
+    }
     try {
-      const sessionId: number = await invoke("create_pty_session", { rows: 24, cols: 80, cwd });
+      const sessionId = await createPtySession(capturedIssueKey, cwd);
 
       // Always save to global state for the captured issue
       const currentState = getIssueTerminalState(capturedIssueKey);
@@ -8782,6 +8779,18 @@ function MainApp({ onLogout }: { onLogout: () => void }) {
     setShowGlobalSettings(false);
   };
 
+  // Open the issue a clicked notification came from; its terminal panel switches to the tab
+  useEffect(() => {
+    const unlisten = listen<NotificationTarget>("notification-activated", ({ payload }) => {
+      if (!payload.issue_key) return;
+      focusIssueTerminal(payload.issue_key, payload.session_id);
+      handleIssueClick(payload.issue_key);
+    });
+    return () => {
+      unlisten.then(fn => fn());
+    };
+  }, []);
+
   const handleIssueCreated = (issueKey: string) => {
     setRefreshTrigger((n) => n + 1);
     setCreateProject(null);